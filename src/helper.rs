@@ -1,15 +1,20 @@
 use std::collections::{HashMap, VecDeque};
 
-use svg::node::element::{Circle, Ellipse, Line, Rectangle, Text};
+use svg::node::element::path::Data;
+use svg::node::element::{Circle, Element, Ellipse, Line, Path, Rectangle, Text};
 use svg::node::{Node, Text as TextNode, Value};
 use svg::Document;
 
+mod font;
 mod line;
 mod marker;
+mod text;
 mod theme;
 mod transform;
+pub use font::*;
 pub use line::*;
 pub use marker::*;
+pub use text::*;
 pub use theme::*;
 pub use transform::*;
 
@@ -181,7 +186,105 @@ impl MakeText for String {
     }
 }
 
-pub fn topological_sort_edges(n: usize, edges: &mut Vec<(usize, usize)>) -> Vec<(usize, usize)> {
+/// 改行ごとに tspan を作るテキスト。x は各行の左端 (text-anchor の基準)
+pub fn make_multiline_text<T: AsRef<str>>(text: T, x: usize, line_height: usize) -> Text {
+    let mut element = Text::new().set("x", x);
+    for (index, line) in text.as_ref().split('\n').enumerate() {
+        let mut tspan = Element::new("tspan");
+        tspan.assign("x", x);
+        tspan.assign("dy", if index == 0 { 0 } else { line_height });
+        tspan.append(TextNode::new(line));
+        element = element.add(tspan);
+    }
+    element
+}
+
+#[test]
+fn make_multiline_text_from_str() {
+    let text = make_multiline_text("a\nb\nc", 10, 12).to_string();
+    assert_eq!(text.matches("<tspan").count(), 3);
+    assert_eq!(text.matches(r#"dy="12""#).count(), 2);
+}
+
+/// 右上の角が折れたノートの形
+pub fn make_note(width: usize, height: usize) -> Path {
+    let fold = NOTE_FOLD.min(width).min(height);
+    let data = Data::new()
+        .move_to((0, 0))
+        .horizontal_line_to(width - fold)
+        .line_to((width, fold))
+        .vertical_line_to(height)
+        .horizontal_line_to(0)
+        .close()
+        .move_to((width - fold, 0))
+        .vertical_line_to(fold)
+        .horizontal_line_to(width);
+    Path::new().set("d", data)
+}
+
+/// ノートの折れた角の大きさ
+pub const NOTE_FOLD: usize = 6;
+
+/// 閉路をなくすために向きを逆にする辺の番号 (edges の添字)
+///
+/// Eades らの貪欲法で頂点 (1-indexed) を並べ、並びに逆らう辺を返す。自己ループは含めない
+pub fn feedback_arc_set(n: usize, edges: &[(usize, usize)]) -> Vec<usize> {
+    let mut removed = vec![false; n + 1];
+    removed[0] = true;
+    let degree = |removed: &[bool], node: usize| {
+        edges
+            .iter()
+            .filter(|&&(from, to)| from != to && !removed[from] && !removed[to])
+            .fold((0_isize, 0_isize), |(ins, outs), &(from, to)| {
+                (ins + (to == node) as isize, outs + (from == node) as isize)
+            })
+    };
+    let mut head = vec![];
+    let mut tail = vec![];
+    while head.len() + tail.len() < n {
+        let remaining = (1..=n).filter(|&node| !removed[node]).collect::<Vec<_>>();
+        let degrees = remaining
+            .iter()
+            .map(|&node| (node, degree(&removed, node)))
+            .collect::<Vec<_>>();
+        // 出ていく辺のない頂点は後ろに、入ってくる辺のない頂点は前に置く。
+        // どちらもなければ出次数と入次数の差が最も大きい頂点を前に置く
+        let node = if let Some(&(node, _)) = degrees.iter().find(|(_, (_, outs))| *outs == 0) {
+            tail.push(node);
+            node
+        } else {
+            let &(node, _) = degrees
+                .iter()
+                .find(|(_, (ins, _))| *ins == 0)
+                .unwrap_or_else(|| {
+                    degrees
+                        .iter()
+                        .rev()
+                        .max_by_key(|(_, (ins, outs))| outs - ins)
+                        .unwrap()
+                });
+            head.push(node);
+            node
+        };
+        removed[node] = true;
+    }
+    tail.reverse();
+    head.extend(tail);
+
+    let mut position = vec![0; n + 1];
+    for (index, &node) in head.iter().enumerate() {
+        position[node] = index;
+    }
+    edges
+        .iter()
+        .enumerate()
+        .filter(|(_, &(from, to))| position[from] > position[to])
+        .map(|(index, _)| index)
+        .collect()
+}
+
+/// 閉路のない辺を、始点のトポロジカル順に並べる
+pub fn topological_sort_edges(n: usize, edges: &mut [(usize, usize)]) -> Vec<(usize, usize)> {
     let mut cnts = vec![0_usize; n];
     let mut order = vec![];
     for edge in edges.iter() {
@@ -199,12 +302,10 @@ pub fn topological_sort_edges(n: usize, edges: &mut Vec<(usize, usize)>) -> Vec<
         // 幅優先探索
         let node = { queue.pop_front().unwrap() };
         order.push(node);
-        for edge in edges.iter() {
-            if cnts[edge.1 - 1] > 0 {
-                cnts[edge.1 - 1] -= 1;
-                if cnts[edge.1 - 1] == 0 {
-                    queue.push_back(edge.1);
-                }
+        for edge in edges.iter().filter(|edge| edge.0 == node) {
+            cnts[edge.1 - 1] -= 1;
+            if cnts[edge.1 - 1] == 0 {
+                queue.push_back(edge.1);
             }
         }
     }
@@ -212,10 +313,10 @@ pub fn topological_sort_edges(n: usize, edges: &mut Vec<(usize, usize)>) -> Vec<
         panic!("topological sort failed");
     }
 
-    println!("order: {:?}", order);
+    // ノード -> 順番
     let mut hashmap = HashMap::new();
-    for (index, o) in order.iter().enumerate() {
-        hashmap.insert(index + 1, o);
+    for (index, &o) in order.iter().enumerate() {
+        hashmap.insert(o, index + 1);
     }
     edges.sort_by(|from, to| {
         (hashmap.get(&from.0), hashmap.get(&from.1)).cmp(&(hashmap.get(&to.0), hashmap.get(&to.1)))
@@ -225,7 +326,7 @@ pub fn topological_sort_edges(n: usize, edges: &mut Vec<(usize, usize)>) -> Vec<
 
 // T is 1-indexed value
 // edge is sorted by topological order
-pub fn calc_rank<T>(nodes: &Vec<T>, edges: &Vec<(usize, usize)>) -> Vec<usize> {
+pub fn calc_rank<T>(nodes: &[T], edges: &[(usize, usize)]) -> Vec<usize> {
     let len = 1 + nodes.len();
     let mut visited = vec![false; len];
     // 仮想ノード0を作成し、そこからエッジがあると考える
@@ -247,3 +348,29 @@ pub fn calc_rank<T>(nodes: &Vec<T>, edges: &Vec<(usize, usize)>) -> Vec<usize> {
     }
     ranks
 }
+
+#[test]
+fn topological_sort_edges_by_source_order() {
+    // 1 -> 2 -> 3 を逆の順に与えても、上から順に並ぶ
+    let mut edges = vec![(2, 3), (1, 2)];
+    let sorted = topological_sort_edges(3, &mut edges);
+    assert_eq!(sorted, vec![(1, 2), (2, 3)]);
+    assert_eq!(calc_rank(&[(); 3], &sorted), vec![1, 1, 2, 3]);
+    // 入次数は自分から出る辺の分だけ減らす
+    let mut edges = vec![(3, 2), (1, 3), (1, 2)];
+    let sorted = topological_sort_edges(3, &mut edges);
+    assert_eq!(calc_rank(&[(); 3], &sorted), vec![1, 1, 3, 2]);
+}
+
+#[test]
+fn feedback_arc_set_breaks_cycles() {
+    assert!(feedback_arc_set(3, &[(1, 2), (2, 3), (1, 3)]).is_empty());
+    // 1 -> 2 -> 3 -> 1 は 1 本逆にすれば閉路がなくなる
+    let mut edges = vec![(1, 2), (2, 3), (3, 1), (2, 2)];
+    assert_eq!(feedback_arc_set(3, &edges), vec![2]);
+    edges[2] = (1, 3);
+    edges.pop();
+    let sorted = topological_sort_edges(3, &mut edges);
+    assert_eq!(calc_rank(&[(); 3], &sorted), vec![1, 1, 2, 3]);
+    assert_eq!(feedback_arc_set(2, &[(1, 2), (2, 1)]).len(), 1);
+}
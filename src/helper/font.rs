@@ -0,0 +1,230 @@
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+use ttf_parser::gpos::{PairAdjustment, PositioningSubtable};
+use ttf_parser::{Face, GlyphId, Tag};
+
+/// TrueType / OpenType のフォント
+///
+/// 読み込むときに解析できることを確かめ、データだけを持つ。
+/// 解析はテーブルの位置を読むだけなので、測るたびに `face` で解析し直す。
+#[derive(Clone)]
+pub struct Font {
+    family: String,
+    data: Arc<[u8]>,
+}
+
+/// フォントを読み込むときのエラー
+#[derive(Debug)]
+pub enum FontError {
+    Io(std::io::Error),
+    /// フォントとして解析できなかった
+    Parse(String),
+}
+
+impl fmt::Display for FontError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FontError::Io(error) => write!(f, "failed to read font: {}", error),
+            FontError::Parse(name) => write!(f, "failed to parse font: {}", name),
+        }
+    }
+}
+
+impl std::error::Error for FontError {}
+
+impl From<std::io::Error> for FontError {
+    fn from(error: std::io::Error) -> Self {
+        FontError::Io(error)
+    }
+}
+
+impl Font {
+    /// ファイルからフォントを読み込む。ファミリー名がなければファイル名を使う。
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, FontError> {
+        let path = path.as_ref();
+        let data = std::fs::read(path)?;
+        let stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Font::from_bytes(data, &stem)
+    }
+
+    pub fn from_bytes(data: Vec<u8>, default_family: &str) -> Result<Self, FontError> {
+        let face =
+            Face::parse(&data, 0).map_err(|_| FontError::Parse(default_family.to_string()))?;
+        let family = face
+            .names()
+            .into_iter()
+            .filter(|name| name.name_id == ttf_parser::name_id::FAMILY && name.is_unicode())
+            .find_map(|name| name.to_string())
+            .unwrap_or_else(|| default_family.to_string());
+        Ok(Font {
+            family,
+            data: data.into(),
+        })
+    }
+
+    #[inline]
+    pub fn family(&self) -> &str {
+        &self.family
+    }
+
+    /// 解析したフォント。from_bytes で解析できたデータなので失敗しない
+    pub fn face(&self) -> Face<'_> {
+        Face::parse(&self.data, 0).expect("font data is parsed in from_bytes")
+    }
+}
+
+/// 横書きのカーニングの値 (フォントの単位)
+///
+/// GPOS の kern 機能のペア調整を優先し、見つからなければ古い kern テーブルを使う
+pub fn kerning(face: &Face, left: GlyphId, right: GlyphId) -> i16 {
+    gpos_kerning(face, left, right)
+        .or_else(|| kern_kerning(face, left, right))
+        .unwrap_or(0)
+}
+
+fn gpos_kerning(face: &Face, left: GlyphId, right: GlyphId) -> Option<i16> {
+    let gpos = face.tables().gpos?;
+    let feature = gpos.features.find(Tag::from_bytes(b"kern"))?;
+    feature
+        .lookup_indices
+        .into_iter()
+        .filter_map(|index| gpos.lookups.get(index))
+        .flat_map(|lookup| lookup.subtables.into_iter::<PositioningSubtable>())
+        .find_map(|subtable| match subtable {
+            PositioningSubtable::Pair(pair) => pair_kerning(&pair, left, right),
+            _ => None,
+        })
+}
+
+fn pair_kerning(pair: &PairAdjustment, left: GlyphId, right: GlyphId) -> Option<i16> {
+    let index = pair.coverage().get(left)?;
+    let (record, _) = match pair {
+        PairAdjustment::Format1 { sets, .. } => sets.get(index)?.get(right)?,
+        PairAdjustment::Format2 {
+            classes, matrix, ..
+        } => matrix.get((classes.0.get(left), classes.1.get(right)))?,
+    };
+    Some(record.x_advance)
+}
+
+fn kern_kerning(face: &Face, left: GlyphId, right: GlyphId) -> Option<i16> {
+    face.tables()
+        .kern?
+        .subtables
+        .into_iter()
+        .filter(|subtable| subtable.horizontal && !subtable.variable && !subtable.has_cross_stream)
+        .find_map(|subtable| subtable.glyphs_kerning(left, right))
+}
+
+#[cfg(test)]
+fn u16s(values: &[u16]) -> Vec<u8> {
+    values.iter().flat_map(|v| v.to_be_bytes()).collect()
+}
+
+#[cfg(test)]
+fn u32s(values: &[u32]) -> Vec<u8> {
+    values.iter().flat_map(|v| v.to_be_bytes()).collect()
+}
+
+/// テスト用の小さなフォント。
+/// 単位は 1000 で、`A` の幅が 600、`V` の幅が 700、`AV` のカーニングが -100。
+#[cfg(test)]
+pub fn make_test_font() -> Vec<u8> {
+    let mut kern = u16s(&[0, 1, 0, 6 + 8 + 6, 0x0001]);
+    kern.extend(u16s(&[1, 6, 0, 0, 1, 2, (-100i16) as u16]));
+    make_font(*b"kern", kern)
+}
+
+/// kern テーブルの代わりに GPOS の kern 機能を持つテスト用のフォント。
+/// `AV` のカーニングが -80。
+#[cfg(test)]
+pub fn make_gpos_test_font() -> Vec<u8> {
+    // ヘッダー, ScriptList (空), FeatureList, LookupList の順に並べる
+    let mut gpos = u16s(&[1, 0, 10, 12, 26, 0]);
+    gpos.extend(u16s(&[1]));
+    gpos.extend(b"kern");
+    gpos.extend(u16s(&[8, 0, 1, 0]));
+    // 種類 2 (ペア調整) の lookup。A (1) と V (2) の組の x_advance を調整する
+    gpos.extend(u16s(&[1, 4, 2, 0, 1, 8]));
+    gpos.extend(u16s(&[1, 12, 0x0004, 0, 1, 18]));
+    gpos.extend(u16s(&[1, 1, 1]));
+    gpos.extend(u16s(&[1, 2, (-80i16) as u16]));
+    make_font(*b"GPOS", gpos)
+}
+
+/// A と V だけを持つフォントに、カーニングのテーブルを 1 つ加える
+#[cfg(test)]
+fn make_font(kerning_tag: [u8; 4], kerning: Vec<u8>) -> Vec<u8> {
+    let mut head = u32s(&[0x0001_0000, 0x0001_0000, 0, 0x5F0F_3CF5]);
+    head.extend(u16s(&[0, 1000]));
+    head.extend([0; 16]);
+    head.extend(u16s(&[0, 0, 1000, 1000, 0, 8, 2, 0, 0]));
+    let mut hhea = u32s(&[0x0001_0000]);
+    hhea.extend(u16s(&[800, (-200i16) as u16, 0, 700, 0, 0, 700, 1, 0, 0]));
+    hhea.extend(u16s(&[0, 0, 0, 0, 0, 3]));
+    let mut maxp = u32s(&[0x0000_5000]);
+    maxp.extend(u16s(&[3]));
+    let hmtx = u16s(&[500, 0, 600, 0, 700, 0]);
+    let mut cmap = u16s(&[0, 1, 3, 10]);
+    cmap.extend(u32s(&[12]));
+    cmap.extend(u16s(&[12, 0]));
+    cmap.extend(u32s(&[16 + 12 * 2, 0, 2, 0x41, 0x41, 1, 0x56, 0x56, 2]));
+
+    let mut tables = [
+        (*b"cmap", cmap),
+        (*b"head", head),
+        (*b"hhea", hhea),
+        (*b"hmtx", hmtx),
+        (kerning_tag, kerning),
+        (*b"maxp", maxp),
+    ];
+    tables.sort_by_key(|(tag, _)| *tag);
+    let mut font = u32s(&[0x0001_0000]);
+    font.extend(u16s(&[tables.len() as u16, 0, 0, 0]));
+    let mut offset = 12 + 16 * tables.len();
+    let mut body = vec![];
+    for (tag, data) in tables.iter() {
+        font.extend_from_slice(tag);
+        font.extend(u32s(&[0, offset as u32, data.len() as u32]));
+        body.extend(data);
+        while body.len() % 4 != 0 {
+            body.push(0);
+        }
+        offset = 12 + 16 * tables.len() + body.len();
+    }
+    font.extend(body);
+    font
+}
+
+#[test]
+fn parse_test_font() {
+    let font = Font::from_bytes(make_test_font(), "test").unwrap();
+    assert_eq!(font.family(), "test");
+    let face = font.face();
+    assert_eq!(face.units_per_em(), 1000);
+    let a = face.glyph_index('A').unwrap();
+    let v = face.glyph_index('V').unwrap();
+    assert_eq!(face.glyph_hor_advance(a), Some(600));
+    assert_eq!(kerning(&face, a, v), -100);
+    assert_eq!(kerning(&face, v, a), 0);
+    assert!(Font::from_bytes(vec![0; 8], "broken").is_err());
+}
+
+#[test]
+fn gpos_kerning_test_font() {
+    let font = Font::from_bytes(make_gpos_test_font(), "gpos").unwrap();
+    let face = font.face();
+    assert!(face.tables().kern.is_none());
+    let a = face.glyph_index('A').unwrap();
+    let v = face.glyph_index('V').unwrap();
+    assert_eq!(kerning(&face, a, v), -80);
+    assert_eq!(kerning(&face, v, a), 0);
+    // 複製しても同じデータを参照する
+    let copy = font.clone();
+    drop(font);
+    assert_eq!(kerning(&copy.face(), a, v), -80);
+}
@@ -1,5 +1,5 @@
 use super::Markers;
-use svg::node::element::Line;
+use svg::node::element::{Line, Path};
 use svg::Node;
 
 pub trait LineMethods
@@ -23,3 +23,4 @@ where
 }
 
 impl LineMethods for Line {}
+impl LineMethods for Path {}
@@ -1,9 +1,21 @@
-use svg::node::element::{Marker, Path};
+use svg::node::element::{Circle, Marker, Path};
 
-#[derive(PartialEq, Eq, Hash, Debug, Clone)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone)]
 pub enum Markers {
     None,
     Array,
+    /// 塗りつぶしのない矢印
+    OpenArray,
+    /// 黒丸 (found message の始点)
+    Circle,
+    /// 矢印 + 黒丸 (lost message の終点)
+    ArrayCircle,
+    /// 白抜きの三角形 (汎化・実現)
+    Triangle,
+    /// 黒塗りのひし形 (コンポジション)
+    Diamond,
+    /// 白抜きのひし形 (集約)
+    OpenDiamond,
 }
 
 impl Markers {
@@ -19,46 +31,80 @@ impl Markers {
     }
 
     pub fn make_svg(&self) -> Marker {
-        let mut marker = Marker::new().set("id", self.get_as_id());
+        let marker = Marker::new()
+            .set("id", self.get_as_id())
+            .set("markerWidth", 5)
+            .set("markerHeight", 5)
+            .set("orient", "auto-start-reverse")
+            .set("refY", "5");
         match self {
-            Markers::Array => {
-                marker = marker
-                    .set("viewBox", (0, 0, 10, 10))
-                    .set("markerWidth", 5)
-                    .set("markerHeight", 5)
-                    .set("orient", "auto-start-reverse")
-                    .set("refX", 10)
-                    .set("refY", "5");
-                marker = marker.add(Path::new().set("d", "M 0 0 L 10 5 L 0 10 z"));
-            }
-            _ => {}
+            Markers::None => marker,
+            Markers::Array => marker
+                .set("viewBox", (0, 0, 10, 10))
+                .set("refX", 10)
+                .add(Path::new().set("d", "M 0 0 L 10 5 L 0 10 z")),
+            Markers::OpenArray => marker.set("viewBox", (0, 0, 10, 10)).set("refX", 10).add(
+                Path::new()
+                    .set("d", "M 0 0 L 10 5 L 0 10")
+                    .set("fill", "none")
+                    .set("stroke", "#000")
+                    .set("stroke-width", 1.5),
+            ),
+            Markers::Circle => marker
+                .set("viewBox", (0, 0, 10, 10))
+                .set("refX", 5)
+                .add(Circle::new().set("cx", 5).set("cy", 5).set("r", 5)),
+            Markers::ArrayCircle => marker
+                .set("viewBox", (0, 0, 20, 10))
+                .set("markerWidth", 10)
+                .set("refX", 10)
+                .add(Path::new().set("d", "M 0 0 L 10 5 L 0 10 z"))
+                .add(Circle::new().set("cx", 15).set("cy", 5).set("r", 5)),
+            Markers::Triangle => marker
+                .set("viewBox", (0, 0, 10, 10))
+                .set("markerWidth", 7)
+                .set("markerHeight", 7)
+                .set("refX", 10)
+                .add(
+                    Path::new()
+                        .set("d", "M 0 0 L 10 5 L 0 10 z")
+                        .set("fill", "#fff")
+                        .set("stroke", "#000"),
+                ),
+            Markers::Diamond => marker
+                .set("viewBox", (0, 0, 20, 10))
+                .set("markerWidth", 10)
+                .set("refX", 20)
+                .add(Path::new().set("d", "M 0 5 L 10 0 L 20 5 L 10 10 z")),
+            Markers::OpenDiamond => marker
+                .set("viewBox", (0, 0, 20, 10))
+                .set("markerWidth", 10)
+                .set("refX", 20)
+                .add(
+                    Path::new()
+                        .set("d", "M 0 5 L 10 0 L 20 5 L 10 10 z")
+                        .set("fill", "#fff")
+                        .set("stroke", "#000"),
+                ),
         }
-        marker
     }
 }
 
-trait MarkerMethod {
-    fn add_marker(self, maker: Markers) -> Option<Marker>;
-}
-
-impl MarkerMethod for Marker {
-    fn add_marker(self, marker: Markers) -> Option<Marker> {
-        match marker {
-            Markers::Array => Some(make_marker()),
-            _ => None,
+#[test]
+fn marker_ids_are_distinct() {
+    let markers = [
+        Markers::Array,
+        Markers::OpenArray,
+        Markers::Circle,
+        Markers::ArrayCircle,
+        Markers::Triangle,
+        Markers::Diamond,
+        Markers::OpenDiamond,
+    ];
+    for (i, a) in markers.iter().enumerate() {
+        for b in markers.iter().skip(i + 1) {
+            assert_ne!(a.get_id(), b.get_id());
         }
     }
+    assert_eq!(Markers::None.get_id(), None);
 }
-
-fn make_marker() -> Marker {
-    Marker::new()
-}
-
-trait MarkerTrait {
-    fn make_mark(&self) -> &Self {
-        // self.add()
-        self
-    }
-}
-
-impl MarkerTrait for Marker {}
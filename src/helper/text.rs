@@ -0,0 +1,196 @@
+use super::font::{kerning, Font, FontError};
+use std::path::Path;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// 文字列の描画幅を測る
+///
+/// フォントがなければ半角 1 文字を font_size とみなす。
+/// フォントがあれば書記素クラスタごとに先頭の文字のグリフを前のフォントから順に探し、
+/// どのフォントにもないクラスタは `text_width` と同じ幅で数える。
+#[derive(Default, Clone)]
+pub struct TextMetrics {
+    fonts: Vec<Font>,
+}
+
+impl TextMetrics {
+    pub fn new() -> Self {
+        TextMetrics::default()
+    }
+
+    /// paths のフォントをこの順の代替チェーンとして読み込む
+    pub fn load<P: AsRef<Path>>(paths: &[P]) -> Result<Self, FontError> {
+        let fonts = paths.iter().map(Font::load).collect::<Result<_, _>>()?;
+        Ok(TextMetrics { fonts })
+    }
+
+    /// 代替チェーンの最後にフォントを追加する
+    pub fn add_font(&mut self, font: Font) {
+        self.fonts.push(font);
+    }
+
+    /// SVG の font-family 属性の値。フォントがなければ None
+    pub fn font_family(&self) -> Option<String> {
+        if self.fonts.is_empty() {
+            return None;
+        }
+        let families: Vec<String> = self
+            .fonts
+            .iter()
+            .map(|font| format!("'{}'", font.family()))
+            .collect();
+        Some(families.join(", "))
+    }
+
+    /// font_size で描いたときの 1 行の幅 (px)
+    pub fn width(&self, text: &str, font_size: usize) -> usize {
+        if self.fonts.is_empty() {
+            return text_width(text) * font_size;
+        }
+        let faces: Vec<_> = self.fonts.iter().map(Font::face).collect();
+        let mut width = 0.0;
+        let mut previous = None;
+        for grapheme in text.graphemes(true) {
+            // 結合文字や ZWJ でつないだ残りの文字は先頭の文字に重ねて描かれるので数えない
+            let c = grapheme.chars().next().unwrap_or_default();
+            let glyph = faces
+                .iter()
+                .enumerate()
+                .find_map(|(index, face)| face.glyph_index(c).map(|glyph| (index, glyph)));
+            match glyph {
+                Some((index, glyph)) => {
+                    let face = &faces[index];
+                    let scale = font_size as f32 / face.units_per_em() as f32;
+                    let mut advance = face.glyph_hor_advance(glyph).unwrap_or(0) as f32;
+                    if let Some((previous_index, previous_glyph)) = previous {
+                        if previous_index == index {
+                            advance += kerning(face, previous_glyph, glyph) as f32;
+                        }
+                    }
+                    width += advance * scale;
+                    previous = Some((index, glyph));
+                }
+                None => {
+                    width += (grapheme_width(grapheme) * font_size) as f32;
+                    previous = None;
+                }
+            }
+        }
+        width.max(0.0).ceil() as usize
+    }
+
+    /// 複数行の文字列のうち最も長い行の幅 (px)
+    pub fn lines_width(&self, text: &str, font_size: usize) -> usize {
+        text.lines()
+            .map(|line| self.width(line, font_size))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// 1 行の幅が max_width (px) 以内になるように折り返す
+    pub fn wrap(&self, text: &str, max_width: usize, font_size: usize) -> Vec<String> {
+        wrap_text_with(text, max_width, |s| self.width(s, font_size))
+    }
+}
+
+/// 半角 1 文字を 1 とした文字列の表示幅。
+/// 書記素クラスタごとに数え、全角文字や絵文字は 2、結合文字だけのものは 0 になる。
+pub fn text_width(text: &str) -> usize {
+    text.graphemes(true).map(grapheme_width).sum()
+}
+
+/// 複数行の文字列のうち最も長い行の表示幅
+pub fn lines_width(text: &str) -> usize {
+    text.lines().map(text_width).max().unwrap_or(0)
+}
+
+#[inline]
+fn grapheme_width(grapheme: &str) -> usize {
+    grapheme.width().min(2)
+}
+
+/// 単語の区切りで 1 行の表示幅が max_width 以内になるように折り返す。
+/// max_width より長い単語は書記素クラスタの区切りで切る。
+pub fn wrap_text(text: &str, max_width: usize) -> Vec<String> {
+    wrap_text_with(text, max_width, text_width)
+}
+
+/// measure で測った幅で折り返す
+fn wrap_text_with<F: Fn(&str) -> usize>(text: &str, max_width: usize, measure: F) -> Vec<String> {
+    let max_width = max_width.max(1);
+    let space = measure(" ");
+    let mut lines = vec![];
+    let mut line = String::new();
+    let mut line_width = 0;
+    for word in text.split_whitespace() {
+        let word_width = measure(word);
+        if line_width > 0 && line_width + space + word_width <= max_width {
+            line.push(' ');
+            line.push_str(word);
+            line_width += space + word_width;
+            continue;
+        }
+        if line_width > 0 {
+            lines.push(std::mem::take(&mut line));
+            line_width = 0;
+        }
+        for grapheme in word.graphemes(true) {
+            let width = measure(grapheme);
+            if line_width > 0 && line_width + width > max_width {
+                lines.push(std::mem::take(&mut line));
+                line_width = 0;
+            }
+            line.push_str(grapheme);
+            line_width += width;
+        }
+    }
+    if !line.is_empty() || lines.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+#[test]
+fn measure_text_width() {
+    assert_eq!(text_width("abc"), 3);
+    assert_eq!(text_width("シーケンス"), 10);
+    assert_eq!(text_width("UML図"), 5);
+    // 結合文字は前の文字と合わせて 1 文字分
+    assert_eq!(text_width("e\u{301}"), 1);
+    // ZWJ でつないだ絵文字は 1 つの絵文字分
+    assert_eq!(text_width("👩\u{200d}💻"), 2);
+    assert_eq!(lines_width("ab\nあいう"), 6);
+}
+
+#[test]
+fn wrap_text_by_words() {
+    assert_eq!(wrap_text("a bb ccc dddd", 6), vec!["a bb", "ccc", "dddd"]);
+    assert_eq!(wrap_text("abcdefgh", 3), vec!["abc", "def", "gh"]);
+    assert_eq!(wrap_text("", 3), vec![""]);
+    assert_eq!(wrap_text("あいうえ", 5), vec!["あい", "うえ"]);
+}
+
+#[test]
+fn measure_with_font() {
+    let mut metrics = TextMetrics::new();
+    assert_eq!(metrics.width("AV", 10), 20);
+    assert_eq!(metrics.font_family(), None);
+    metrics.add_font(Font::from_bytes(super::font::make_test_font(), "Test Sans").unwrap());
+    // 600 + 700 - 100 (カーニング) を 1000 単位から 10px に
+    assert_eq!(metrics.width("AV", 10), 12);
+    assert_eq!(metrics.width("VA", 10), 13);
+    // フォントにない文字は半角幅で数える
+    assert_eq!(metrics.width("A図", 10), 6 + 20);
+    // 書記素クラスタごとに数えるので、ZWJ でつないだ絵文字は 1 つ分
+    assert_eq!(metrics.width("A👩\u{200d}💻", 10), 6 + 20);
+    // 結合文字はフォントにあってもなくても前の文字に重ねる
+    assert_eq!(metrics.width("A\u{301}V", 10), 12);
+    assert_eq!(metrics.lines_width("A\nAVA", 10), 18);
+    assert_eq!(metrics.wrap("AV AV AV", 40, 10), vec!["AV AV", "AV"]);
+    assert_eq!(metrics.font_family().as_deref(), Some("'Test Sans'"));
+
+    // GPOS だけにカーニングを持つフォント: 600 + 700 - 80
+    let mut metrics = TextMetrics::new();
+    metrics.add_font(Font::from_bytes(super::font::make_gpos_test_font(), "GPOS Sans").unwrap());
+    assert_eq!(metrics.width("AV", 100), 122);
+}
@@ -1,5 +1,5 @@
 use crate::theme::Theme;
-use svg::node::element::{Circle, Ellipse, Rectangle};
+use svg::node::element::{Circle, Ellipse, Path, Rectangle};
 
 pub trait SetTheme {
     fn set_theme(self, theme: &Theme) -> Self;
@@ -28,3 +28,11 @@ impl SetTheme for Ellipse {
             .set("stroke-width", 1)
     }
 }
+
+impl SetTheme for Path {
+    fn set_theme(self, theme: &Theme) -> Self {
+        self.set("fill", theme.color.rect.fill)
+            .set("stroke", theme.color.rect.frame)
+            .set("stroke-width", 1)
+    }
+}
@@ -7,7 +7,7 @@ pub mod uml;
 
 #[macro_export]
 macro_rules! make_vec {
-  ( $( $x:expr ),* ) => ( vec![ $( crate::helper::make_pair($x) ),* ] );
+  ( $( $x:expr ),* ) => ( vec![ $( $crate::helper::make_pair($x) ),* ] );
 }
 
 pub trait MakeSvg {
@@ -1,7 +1,6 @@
-use std::collections::{HashMap, VecDeque};
-
-use uml_generator::{helper::BackgroundColor, theme::ThemeName, uml::ClassUML, MakeSvg};
+use uml_generator::{helper::BackgroundColor, uml::ClassUML, MakeSvg};
 
+#[allow(dead_code)]
 #[derive(Debug, Clone)]
 struct Node {
     id: usize,
@@ -25,7 +24,7 @@ fn node(id: usize, name: &str) -> Node {
 
 fn main() {
     println!("Hello, world!");
-    let nodes = vec![
+    let nodes = [
         node(1, "a"),
         node(2, "b"),
         node(3, "c"),
@@ -40,7 +39,7 @@ fn main() {
     // 3 -> 6
     // topological_sort(nodes.len(), &mut edges);
     println!("{:?}", edges);
-    let ranks = vec![1]; //calc(&nodes, &edges);
+    let ranks = [1]; //calc(&nodes, &edges);
     let mut graph = vec![vec![]; nodes.len() + 1];
     for i in 1..ranks.len() {
         graph[ranks[i]].push(nodes[i - 1].clone());
@@ -65,7 +64,7 @@ fn main() {
     s.add_class("test5", &[], &[]);
     s.add_class("test6", &[], &[]);
     s.add_class("test7", &[], &[]);
-    s.add_edges(edges.as_slice());
+    s.add_edges(edges.as_slice()).unwrap();
 
     let svg = s.make_svg().change_background_color("#fff".into());
 
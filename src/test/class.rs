@@ -1,16 +1,21 @@
-use crate::{helper::*, uml::ClassUML, MakeSvg};
+use super::{attribute, element_before, elements, text_element};
+use crate::{
+    helper::*,
+    uml::{ClassElement, ClassError, ClassKind, ClassMethod, ClassUML, Relation, Visibility},
+    MakeSvg,
+};
 
 #[test]
 fn test_class() {
     let mut c = ClassUML::new("hello world");
 
-    c.add_class(
+    c.add_simple_class(
         "class element",
         &[(false, "element"), (true, "element2")],
         &[(true, "method1"), (true, "method2"), (false, "method3")],
     );
 
-    c.add_class(
+    c.add_simple_class(
         "class element2",
         &[(false, "element"), (true, "element2")],
         &[(true, "method1"), (true, "method2"), (false, "method3")],
@@ -20,3 +25,616 @@ fn test_class() {
 
     svg::save("img/class.svg", &svg).unwrap();
 }
+
+#[test]
+fn test_class_note() {
+    let mut c = ClassUML::new("note");
+    c.add_class("a", &[], &[]);
+    c.add_class("b", &[], &[]);
+    c.add_edges(&[(1, 2)]).unwrap();
+    c.make_svg();
+    let (_, _, width, _) = c.bounding_box();
+
+    c.add_note(1, "first line\nsecond line").unwrap();
+    let svg = c.make_svg().to_string();
+    assert!(c.bounding_box().2 > width);
+    assert_eq!(svg.matches("<tspan").count(), 2);
+    assert_eq!(svg.matches("stroke-dasharray").count(), 1);
+}
+
+#[test]
+fn test_class_note_unicode_width() {
+    let width = |text: &str| {
+        let mut c = ClassUML::new("note");
+        c.add_class("a", &[], &[]);
+        c.add_note(1, text).unwrap();
+        c.make_svg();
+        c.bounding_box().2
+    };
+    assert_eq!(width("クラス図"), width("abcdefgh"));
+}
+
+#[test]
+fn test_class_font_metrics() {
+    let mut metrics = TextMetrics::new();
+    metrics.add_font(Font::from_bytes(make_test_font(), "Test Sans").unwrap());
+    let mut c = ClassUML::new("font");
+    c.add_class("AVAVAVAVAVAVAVAVAVAV", &[], &[]);
+    c.make_svg();
+    let width = c.bounding_box().2;
+    c.set_metrics(metrics);
+    let svg = c.make_svg().to_string();
+    assert!(c.bounding_box().2 < width);
+    assert!(svg.contains(r#"font-family="'Test Sans'""#));
+}
+
+#[test]
+fn test_class_relations() {
+    let mut c = ClassUML::new("relations");
+    for name in ["shape", "circle", "drawable", "canvas", "layer", "logger"] {
+        c.add_class(name, &[], &[]);
+    }
+    c.add_relations(&[
+        (2, 1, Relation::Inheritance),
+        (1, 3, Relation::Realization),
+        (4, 5, Relation::Composition),
+        (5, 1, Relation::Aggregation),
+        (4, 6, Relation::Dependency),
+        (4, 2, Relation::Association { navigable: true }),
+    ])
+    .unwrap();
+    let svg = c.make_svg().to_string();
+    assert_eq!(svg.matches("<marker").count(), 4);
+    assert_eq!(svg.matches("stroke-dasharray").count(), 2);
+    assert_eq!(svg.matches("marker-start").count(), 2);
+    assert_eq!(svg.matches("marker-end").count(), 4);
+
+    // 親クラスは子クラスより上に置き、子の上辺から親の下辺へ矢印を引く
+    let mut c = ClassUML::new("inheritance");
+    c.add_class("base", &[], &[]);
+    c.add_class("derived", &[], &[]);
+    c.add_relation(2, 1, Relation::Inheritance).unwrap();
+    let svg = c.make_svg().to_string();
+    let (base_x, base_y, base_width, base_height) = class_box(&svg, "base");
+    let (_, derived_y, _, _) = class_box(&svg, "derived");
+    assert!(base_y + base_height < derived_y);
+    let (x1, y1, x2, y2) = edge_line(&svg, "url(#marker-5)");
+    assert_eq!(x1, x2);
+    assert!(base_x < x1 && x1 < base_x + base_width);
+    assert_eq!((y1, y2), (derived_y, base_y + base_height));
+}
+
+#[test]
+fn test_class_edge_labels() {
+    let mut c = ClassUML::new("labels");
+    let user = c.add_class("user", &[], &[]);
+    let order = c.add_class("order", &[], &[]);
+    c.add_edge(user, order, Relation::Association { navigable: true })
+        .unwrap()
+        .set_from_end("1", "")
+        .set_to_end("0..*", "orders")
+        .set_label("places");
+    let svg = c.make_svg().to_string();
+    let (_, user_y, _, user_height) = class_box(&svg, "user");
+    let (_, order_y, _, _) = class_box(&svg, "order");
+    let (line_x, _, _, _) = edge_line(&svg, "url(#marker-2)");
+    // 文字が入るように階層の間を広げ、すべてのラベルを箱の間に置く
+    let (gap_top, gap_bottom) = (user_y + user_height, order_y);
+    for label in ["1", "0..*", "orders", "places"] {
+        let text = text_element(&svg, label);
+        let y = attribute(text, "y");
+        assert!(gap_top + attribute(text, "font-size") <= y, "{}", label);
+        assert!(y <= gap_bottom, "{}", label);
+    }
+    // 多重度は線の右、ロール名は線の左に、それぞれの端の近くに置く
+    let (anchor, x, from_y) = text_at(&svg, "1");
+    assert!(anchor == "start" && x > line_x);
+    let (anchor, x, to_y) = text_at(&svg, "0..*");
+    assert!(anchor == "start" && x > line_x);
+    let (anchor, x, role_y) = text_at(&svg, "orders");
+    assert!(anchor == "end" && x < line_x);
+    assert!(from_y < role_y && role_y == to_y);
+    // 関係の名前は両端のラベルの間で、線の右に置く
+    let (anchor, x, y) = text_at(&svg, "places");
+    assert!(anchor == "start" && x > line_x);
+    assert!(from_y < y && y < to_y);
+}
+
+#[test]
+fn test_class_long_role() {
+    let mut c = ClassUML::new("long role");
+    let customer = c.add_class("customer", &[], &[]);
+    let order = c.add_class("order", &[], &[]);
+    let role = "ordersPlacedByThisCustomer";
+    c.add_edge(customer, order, Relation::Association { navigable: true })
+        .unwrap()
+        .set_to_end("0..*", role);
+    let svg = c.make_svg().to_string();
+    let origin = svg.split("<g transform=\"translate(").nth(1).unwrap();
+    let origin_x: usize = origin[..origin.find(',').unwrap()].parse().unwrap();
+    let view_width = svg.split("viewBox=\"0 0 ").nth(1).unwrap();
+    let view_width: usize = view_width[..view_width.find(' ').unwrap()].parse().unwrap();
+    // フォントがないときの幅で、ロール名の左端も図の箱も viewBox に収まる
+    let (anchor, x, _) = text_at(&svg, role);
+    let width = role.len() * attribute(text_element(&svg, role), "font-size");
+    assert!(anchor == "end" && width <= origin_x + x);
+    let (box_x, _, box_width, _) = class_box(&svg, "order");
+    assert!(origin_x + box_x + box_width <= view_width);
+    let (line_x, _, _, _) = edge_line(&svg, "url(#marker-2)");
+    assert!(x < line_x);
+}
+
+/// name を名前に持つパッケージの枠のタブと本体の (左, 上, 幅, 高さ)
+fn frame(svg: &str, name: &str) -> (Rect, Rect) {
+    let body = element_before(svg, "<rect", name);
+    let start = svg.find(body).unwrap();
+    let tab = &svg[svg[..start].rfind("<rect").unwrap()..start];
+    let rect = |element: &str| {
+        (
+            attribute(element, "x"),
+            attribute(element, "y"),
+            attribute(element, "width"),
+            attribute(element, "height"),
+        )
+    };
+    (rect(tab), rect(body))
+}
+
+/// 折れ線の関係の線の頂点の一覧
+fn routes(svg: &str) -> Vec<Vec<(usize, usize)>> {
+    svg.split("<path d=\"M")
+        .skip(1)
+        .filter(|path| path.split("/>").next().unwrap().contains("marker-end"))
+        .map(|path| {
+            path[..path.find('"').unwrap()]
+                .split(" L")
+                .map(|point| {
+                    let (x, y) = point.split_once(',').unwrap();
+                    (x.parse().unwrap(), y.parse().unwrap())
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// (左, 上, 幅, 高さ)
+type Rect = (usize, usize, usize, usize);
+
+/// point が rect の内側 (辺を含まない) にあるか
+fn contains((x, y, width, height): Rect, (px, py): (usize, usize)) -> bool {
+    x < px && px < x + width && y < py && py < y + height
+}
+
+fn encloses(outer: Rect, (x, y, width, height): Rect) -> bool {
+    contains(outer, (x, y)) && contains(outer, (x + width, y + height))
+}
+
+fn overlaps((x, y, width, height): Rect, other: Rect) -> bool {
+    x < other.0 + other.2 && other.0 < x + width && y < other.1 + other.3 && other.1 < y + height
+}
+
+/// 縦か横の線分 a-b が rect の内側を通るか
+fn hits(a: (usize, usize), b: (usize, usize), rect: Rect) -> bool {
+    let (left, right) = (a.0.min(b.0), a.0.max(b.0));
+    let (top, bottom) = (a.1.min(b.1), a.1.max(b.1));
+    overlaps(rect, (left, top, right - left, bottom - top))
+}
+
+/// 縦か横の線分どうしが同じ直線の上で重なるか
+fn shares_segment(s: &[(usize, usize)], t: &[(usize, usize)]) -> bool {
+    let span = |a: usize, b: usize| (a.min(b), a.max(b));
+    let overlap = |(s0, s1): (usize, usize), (t0, t1): (usize, usize)| s0.max(t0) < s1.min(t1);
+    let vertical = s[0].0 == s[1].0 && t[0].0 == t[1].0 && s[0].0 == t[0].0;
+    let horizontal = s[0].1 == s[1].1 && t[0].1 == t[1].1 && s[0].1 == t[0].1;
+    (vertical && overlap(span(s[0].1, s[1].1), span(t[0].1, t[1].1)))
+        || (horizontal && overlap(span(s[0].0, s[1].0), span(t[0].0, t[1].0)))
+}
+
+/// 折れ線が rect の辺を横切る回数
+fn crossings(route: &[(usize, usize)], rect: Rect) -> usize {
+    route
+        .windows(2)
+        .map(
+            |pair| match (contains(rect, pair[0]), contains(rect, pair[1])) {
+                (true, true) => 0,
+                (false, false) if hits(pair[0], pair[1], rect) => 2,
+                (false, false) => 0,
+                _ => 1,
+            },
+        )
+        .sum()
+}
+
+/// name を名前に持つクラスの箱の (左, 上, 幅, 高さ)
+fn class_box(svg: &str, name: &str) -> Rect {
+    let end = svg.find(&format!(">\n{}\n", name)).unwrap();
+    let start = svg[..end].rfind("<g transform=\"translate(").unwrap();
+    let translate = &svg[start + 24..];
+    let (x, y) = translate[..translate.find(')').unwrap()]
+        .split_once(", ")
+        .unwrap();
+    let rect = &svg[start..end];
+    (
+        x.parse().unwrap(),
+        y.parse().unwrap(),
+        attribute(rect, "width"),
+        attribute(rect, "height"),
+    )
+}
+
+/// marker を終端に付けた最初の線の (x1, y1, x2, y2)
+fn edge_line(svg: &str, marker: &str) -> (usize, usize, usize, usize) {
+    let start = svg
+        .find(&format!("<line marker-end=\"{}\"", marker))
+        .unwrap();
+    let line = &svg[start..start + svg[start..].find("/>").unwrap()];
+    (
+        attribute(line, "x1"),
+        attribute(line, "y1"),
+        attribute(line, "x2"),
+        attribute(line, "y2"),
+    )
+}
+
+/// label を書いた最初の text 要素の (text-anchor, x, y)
+fn text_at<'a>(svg: &'a str, label: &str) -> (&'a str, usize, usize) {
+    let text = text_element(svg, label);
+    let anchor = text.split(" text-anchor=\"").nth(1).unwrap();
+    (
+        &anchor[..anchor.find('"').unwrap()],
+        attribute(text, "x"),
+        attribute(text, "y"),
+    )
+}
+
+#[test]
+fn test_class_handles() {
+    let mut c = ClassUML::new("handles");
+    let animal = c.add_class("animal", &[], &[]);
+    let dog = c.add_class("dog", &[], &[]);
+    c.add_class("cat", &[], &[]);
+    c.add_relation(dog, animal, Relation::Inheritance).unwrap();
+    c.add_relation("cat", "animal", Relation::Inheritance)
+        .unwrap();
+    c.add_note(dog, "good boy").unwrap();
+    assert_eq!(
+        c.add_relation("cat", "bird", Relation::Dependency).err(),
+        Some(ClassError::UnknownClass("bird".into()))
+    );
+    assert_eq!(
+        c.add_edges(&[(1, 4)]).err(),
+        Some(ClassError::InvalidIndex(4))
+    );
+    assert!(c.add_note("fish", "").is_err());
+    // 失敗した関係は追加されない
+    let svg = c.make_svg().to_string();
+    assert_eq!(svg.matches("marker-end").count(), 2);
+    assert_eq!(svg.matches("stroke-dasharray").count(), 1);
+}
+
+#[test]
+fn test_class_dynamic_size() {
+    let size = |elements: &[(bool, &str)], methods: &[(bool, &str)]| {
+        let mut c = ClassUML::new("size");
+        c.add_simple_class("a", elements, methods);
+        c.make_svg();
+        let (_, _, width, height) = c.bounding_box();
+        (width, height)
+    };
+    let (width, height) = size(&[], &[]);
+    let (_, taller) = size(&[(true, "x"), (true, "y")], &[(true, "f")]);
+    assert_eq!(taller, height + 8 * 3 + 3 * 3);
+    let (wider, _) = size(&[], &[(true, "a_really_long_method_name_that_overflows")]);
+    assert!(wider > width);
+
+    let mut c = ClassUML::new("size");
+    c.add_simple_class("a", &[(true, "x"), (true, "y")], &[(true, "f")]);
+    let svg = c.make_svg().to_string();
+    // 箱と区画の境界線が中身に合わせて伸びる
+    assert!(svg.contains(r#"height="69""#));
+    assert!(svg.contains(r#"x1="0" x2="100" y1="48" y2="48""#));
+}
+
+#[test]
+fn test_class_members() {
+    let mut c = ClassUML::new("members");
+    c.add_class(
+        "shape",
+        &[
+            ClassElement::new(Visibility::Protected, "name").with_type("String"),
+            ClassElement::new(Visibility::Package, "count")
+                .with_type("usize")
+                .with_default("0")
+                .with_static(),
+        ],
+        &[
+            ClassMethod::new(Visibility::Public, "area")
+                .with_param("scale", "f64")
+                .with_return("f64")
+                .with_abstract(),
+            ClassMethod::new(Visibility::Private, "unit")
+                .with_return("Self")
+                .with_static(),
+        ],
+    );
+    let svg = c.make_svg().to_string();
+    assert!(svg.contains("\n#\n"));
+    assert!(svg.contains("\n~\n"));
+    assert!(svg.contains("\nname: String\n"));
+    assert!(svg.contains("\narea(scale: f64): f64\n"));
+    assert!(svg.contains("text-decoration=\"underline\" x=\"8\">\ncount: usize = 0\n"));
+    assert!(svg.contains("font-style=\"italic\" x=\"8\">\narea"));
+    assert!(svg.contains("text-decoration=\"underline\" x=\"8\">\nunit(): Self\n"));
+}
+
+#[test]
+fn test_class_kinds() {
+    let mut c = ClassUML::new("kinds");
+    let shape = c.add_class("Shape", &[], &[]);
+    let drawable = c.add_class("Drawable", &[], &[]);
+    let color = c.add_enum("Color", &["RED", "GREEN", "BLUE"]);
+    c.set_kind(shape, ClassKind::Abstract).unwrap();
+    c.set_kind(drawable, ClassKind::Interface).unwrap();
+    c.add_stereotype(drawable, "entity").unwrap();
+    assert!(c.set_kind("Point", ClassKind::Record).is_err());
+    c.add_relation(shape, drawable, Relation::Realization)
+        .unwrap();
+    c.add_relation(shape, color, Relation::Dependency).unwrap();
+    let svg = c.make_svg().to_string();
+    assert!(svg.contains("\n«interface, entity»\n"));
+    assert!(svg.contains("\n«abstract»\n"));
+    assert!(svg.contains("\n«enumeration»\n"));
+    // ステレオタイプは名前の上に、どちらも名前の区画の中央に置く
+    for (name, stereotype) in [
+        ("Shape", "«abstract»"),
+        ("Drawable", "«interface, entity»"),
+        ("Color", "«enumeration»"),
+    ] {
+        let (_, _, width, height) = class_box(&svg, name);
+        let (anchor, x, name_y) = text_at(&svg, name);
+        assert!(anchor == "middle" && x == width / 2, "{}", name);
+        let (anchor, x, stereotype_y) = text_at(&svg, stereotype);
+        assert!(anchor == "middle" && x == width / 2, "{}", name);
+        let font_size = attribute(text_element(&svg, stereotype), "font-size");
+        assert!(
+            font_size <= stereotype_y && stereotype_y < name_y,
+            "{}",
+            name
+        );
+        assert!(name_y <= height, "{}", name);
+    }
+    // 抽象クラスの名前だけ斜体
+    assert!(text_element(&svg, "Shape").contains("font-style=\"italic\""));
+    assert!(!text_element(&svg, "Drawable").contains("font-style"));
+    assert!(svg.contains("\nGREEN\n"));
+    assert!(!svg.contains("<circle"));
+
+    c.show_badges(true);
+    let svg = c.make_svg().to_string();
+    assert_eq!(svg.matches("<circle").count(), 3);
+    assert!(svg.contains("\nI\n"));
+
+    // 種類を表す丸の分だけ名前の区画が広がる
+    let width = |badges: bool| {
+        let mut c = ClassUML::new("badge");
+        c.add_class("AbstractShapeFactory", &[], &[]);
+        c.show_badges(badges);
+        c.make_svg();
+        c.bounding_box().2
+    };
+    assert_eq!(width(true), width(false) + 30);
+}
+
+#[test]
+fn test_class_type_params() {
+    let mut c = ClassUML::new("generics");
+    let repository = c.add_class("Repository", &[], &[]);
+    c.make_svg();
+    let (_, _, width, height) = c.bounding_box();
+
+    c.add_type_param(repository, "T", "Entity").unwrap();
+    assert!(c.add_type_param("Service", "T", "").is_err());
+    let svg = c.make_svg().to_string();
+    // 右上の角に重なる破線の箱
+    let (class_x, class_y, class_width, class_height) = class_box(&svg, "Repository");
+    let translate = element_before(&svg, "<g transform=\"translate(", "T: Entity");
+    let translate = &translate[translate.find('(').unwrap() + 1..translate.find(')').unwrap()];
+    let (x, y) = translate.split_once(", ").unwrap();
+    let (x, y): (i32, i32) = (x.parse().unwrap(), y.parse().unwrap());
+    let rect = element_before(&svg, "<rect", "T: Entity");
+    assert!(rect.contains("stroke-dasharray=\"3\""));
+    let (box_width, box_height) = (attribute(rect, "width"), attribute(rect, "height"));
+    assert!(0 < x && x < class_width as i32);
+    assert!(x + box_width as i32 > class_width as i32);
+    assert!(y < 0 && y + (box_height as i32) > 0 && y + (box_height as i32) < class_height as i32);
+    // 箱のはみ出しの分だけクラスを下げ、全体も広げる
+    assert_eq!(class_y as i32 + y, 0);
+    assert!(class_x as i32 + x + box_width as i32 <= c.bounding_box().2 as i32);
+    assert_eq!(c.bounding_box().2, width + 10);
+    assert_eq!(c.bounding_box().3, height + 7);
+}
+
+#[test]
+fn test_class_packages() {
+    let mut c = ClassUML::new("packages");
+    let user = c.add_class("User", &[], &[]);
+    let order = c.add_class("Order", &[], &[]);
+    let repository = c.add_class("Repository", &[], &[]);
+    let main = c.add_class("Main", &[], &[]);
+    c.add_relation(order, user, Relation::Association { navigable: true })
+        .unwrap();
+    c.add_relation(repository, user, Relation::Dependency)
+        .unwrap();
+    c.add_relation(repository, order, Relation::Dependency)
+        .unwrap();
+    c.add_relation(main, repository, Relation::Dependency)
+        .unwrap();
+    let domain = c.add_package("domain", &[user, order]).unwrap();
+    c.add_subpackage(domain, "infra", &[repository]).unwrap();
+    assert_eq!(
+        c.add_package("other", &[user]).unwrap_err(),
+        ClassError::AlreadyPackaged("User".to_string())
+    );
+    assert!(c.add_package("other", &["Service"]).is_err());
+    let svg = c.make_svg().to_string();
+    // タブと本体の枠がパッケージごとに 1 つずつ、タブに名前を書く
+    let (domain_tab, domain) = frame(&svg, "domain");
+    let (infra_tab, infra) = frame(&svg, "infra");
+    for (tab, body) in [(domain_tab, domain), (infra_tab, infra)] {
+        assert!(tab.0 == body.0 && tab.1 + tab.3 == body.1 && tab.2 < body.2);
+    }
+    // メンバーは枠の中に並び、入れ子のパッケージは外のパッケージの中に入る
+    let boxes = ["User", "Order", "Repository", "Main"].map(|name| class_box(&svg, name));
+    let [user_box, order_box, repository_box, main_box] = boxes;
+    assert!(encloses(domain, user_box) && encloses(domain, order_box));
+    assert!(!encloses(infra, user_box) && !encloses(infra, order_box));
+    assert!(encloses(infra, repository_box) && encloses(domain, infra_tab));
+    assert!(!overlaps(domain_tab, main_box) && !overlaps(domain, main_box));
+    // 同じパッケージの中は直線
+    let (x1, y1, x2, y2) = edge_line(&svg, "url(#marker-2)");
+    assert!(x1 == x2 && y1 == order_box.1 + order_box.3 && y2 == user_box.1);
+    // パッケージをまたぐ線は、端のクラスを含む枠だけを 1 度ずつ横切り、
+    // ほかのクラスの箱やタブには重ならない
+    let paths = routes(&svg);
+    assert_eq!(paths.len(), 3);
+    for route in &paths {
+        let ends = [route[0], route[route.len() - 1]];
+        for body in [domain, infra] {
+            let inside = ends.iter().filter(|&&end| contains(body, end)).count();
+            assert_eq!(crossings(route, body), inside % 2, "{:?}", route);
+        }
+        for obstacle in boxes.iter().chain([&domain_tab, &infra_tab]) {
+            assert!(
+                route
+                    .windows(2)
+                    .all(|pair| !hits(pair[0], pair[1], *obstacle)),
+                "{:?}",
+                route
+            );
+        }
+    }
+
+    // パッケージごとに 1 つの箱にまとめ、依存を数える
+    c.add_relation(main, user, Relation::Association { navigable: true })
+        .unwrap();
+    let mut view = c.package_view();
+    let svg = view.make_svg().to_string();
+    // パッケージの箱 (タブと本体) と Main
+    assert_eq!(svg.matches("<rect").count(), 3);
+    assert!(svg.contains("\ndomain\n"));
+    assert!(!svg.contains("\nUser\n"));
+    assert!(svg.contains("\nMain\n"));
+    assert_eq!(svg.matches("stroke-dasharray=\"4\"").count(), 1);
+    assert!(svg.contains("\n2\n"));
+
+    // 最上位の通り道を通る線のラベルは、通り道の上に空けた余白に置く
+    for reversed in [false, true] {
+        let mut c = ClassUML::new("labels");
+        let d = c.add_class("D", &[], &[]);
+        let p = c.add_class("P", &[], &[]);
+        let q = c.add_class("Q", &[], &[]);
+        c.add_package("P", &[p]).unwrap();
+        c.add_package("Q", &[q]).unwrap();
+        let (from, to) = if reversed { (q, d) } else { (d, q) };
+        c.add_edge(from, to, Relation::Dependency)
+            .unwrap()
+            .set_label("uses");
+        let svg = c.make_svg().to_string();
+        let route = &routes(&svg)[0];
+        let top = route.iter().map(|&(_, y)| y).min().unwrap();
+        let (_, p_frame) = frame(&svg, "P");
+        let (q_tab, _) = frame(&svg, "Q");
+        assert!(top < p_frame.1 && top < q_tab.1);
+        let (left, right) = route
+            .windows(2)
+            .find(|pair| pair[0].1 == top && pair[1].1 == top)
+            .map(|pair| (pair[0].0.min(pair[1].0), pair[0].0.max(pair[1].0)))
+            .unwrap();
+        let (anchor, x, y) = text_at(&svg, "uses");
+        let font_size = attribute(text_element(&svg, "uses"), "font-size");
+        assert!(anchor == "middle" && left < x && x < right);
+        assert!(font_size <= y && y <= top);
+    }
+}
+
+#[test]
+fn test_class_cycles() {
+    let mut c = ClassUML::new("cycles");
+    let a = c.add_class("A", &[], &[]);
+    let b = c.add_class("B", &[], &[]);
+    let d = c.add_class("D", &[], &[]);
+    let navigable = Relation::Association { navigable: true };
+    c.add_relations(&[(a, b, navigable), (b, d, navigable), (d, a, navigable)])
+        .unwrap();
+    c.add_relation(b, a, Relation::Dependency).unwrap();
+    c.add_relation(d, d, navigable).unwrap();
+    // B -> D -> A と並べ、A -> B だけを逆にして階層を決める
+    assert_eq!(c.get_ranks(), vec![1, 3, 1, 2]);
+    let svg = c.make_svg().to_string();
+    let boxes = ["A", "B", "D"].map(|name| class_box(&svg, name));
+    let [a_box, b_box, d_box] = boxes;
+    let mut lines = routes(&svg);
+    lines.extend(elements(&svg, "<line marker").into_iter().map(|line| {
+        vec![
+            (attribute(line, "x1"), attribute(line, "y1")),
+            (attribute(line, "x2"), attribute(line, "y2")),
+        ]
+    }));
+    assert_eq!(lines.len(), 5);
+    // 逆にした辺も元の向きのまま、下の A から上の B へ矢印を引く
+    let (a_top, b_bottom) = (a_box.1, b_box.1 + b_box.3);
+    let ends = |line: &Vec<(usize, usize)>| (line[0], line[line.len() - 1]);
+    assert!(lines
+        .iter()
+        .any(|line| ends(line).0 .1 == a_top && ends(line).1 .1 == b_bottom));
+    assert!(lines
+        .iter()
+        .any(|line| ends(line).0 .1 == b_bottom && ends(line).1 .1 == a_top));
+    // 自分自身への関係は右下の角を回る
+    let (d_right, d_bottom) = (d_box.0 + d_box.2, d_box.1 + d_box.3);
+    assert!(lines
+        .iter()
+        .any(|line| ends(line).0 .1 == d_bottom && ends(line).1 .0 == d_right));
+    // 間の階層の D を飛び越える線も含め、どの線もクラスの箱を横切らず、線どうしも重ならない
+    for (index, line) in lines.iter().enumerate() {
+        for rect in boxes {
+            assert!(
+                line.windows(2).all(|pair| !hits(pair[0], pair[1], rect)),
+                "{:?}",
+                line
+            );
+        }
+        for other in &lines[index + 1..] {
+            for pair in line.windows(2) {
+                assert!(
+                    other
+                        .windows(2)
+                        .all(|other_pair| !shares_segment(pair, other_pair)),
+                    "{:?} {:?}",
+                    line,
+                    other
+                );
+            }
+        }
+    }
+    let right = lines.iter().flatten().map(|&(x, _)| x).max().unwrap();
+    assert!(10 + right < c.bounding_box().2);
+
+    // 隣り合う階層の間の逆向きの線も、左右にずらして重ねない
+    let mut c = ClassUML::new("bidirectional");
+    let p = c.add_class("P", &[], &[]);
+    let q = c.add_class("Q", &[], &[]);
+    c.add_relation(p, q, navigable).unwrap();
+    c.add_relation(q, p, Relation::Dependency).unwrap();
+    let svg = c.make_svg().to_string();
+    let lines = elements(&svg, "<line marker");
+    assert_eq!(lines.len(), 2);
+    let xs = lines
+        .iter()
+        .map(|line| attribute(line, "x1"))
+        .collect::<Vec<_>>();
+    assert!(lines
+        .iter()
+        .all(|line| attribute(line, "x2") == attribute(line, "x1")));
+    assert!(xs[0] != xs[1]);
+}
@@ -1,2 +1,27 @@
 mod class;
 mod sequence;
+
+/// label を書いた最初の text 要素の開始タグ
+fn text_element<'a>(svg: &'a str, label: &str) -> &'a str {
+    element_before(svg, "<text", label)
+}
+
+/// label を書いた最初の text 要素より前にある、最後の tag 要素の開始タグ
+fn element_before<'a>(svg: &'a str, tag: &str, label: &str) -> &'a str {
+    let end = svg.find(&format!(">\n{}\n", label)).unwrap();
+    let start = svg[..end].rfind(tag).unwrap();
+    &svg[start..start + svg[start..].find('>').unwrap()]
+}
+
+/// tag 要素の開始タグの一覧
+fn elements<'a>(svg: &'a str, tag: &str) -> Vec<&'a str> {
+    svg.match_indices(tag)
+        .map(|(start, _)| &svg[start..start + svg[start..].find('>').unwrap()])
+        .collect()
+}
+
+/// 要素の中の最初の name 属性の値
+fn attribute(element: &str, name: &str) -> usize {
+    let value = element.split(&format!(" {}=\"", name)).nth(1).unwrap();
+    value[..value.find('"').unwrap()].parse().unwrap()
+}
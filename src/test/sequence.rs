@@ -1,5 +1,13 @@
+use super::{attribute, element_before, elements, text_element};
 use crate::helper::Markers;
-use crate::{helper::*, theme::ThemeName, uml::Sequence, MakeSvg};
+use crate::{
+    helper::*,
+    theme::ThemeName,
+    uml::{
+        FragmentKind, Gate, MessageKind, NotePosition, ParticipantKind, Sequence, SequenceError,
+    },
+    MakeSvg,
+};
 
 #[test]
 fn test_sequence() {
@@ -9,12 +17,723 @@ fn test_sequence() {
     s.add_node("test2");
     s.add_node("test3");
     s.add_node("test4");
-    s.add_edge(("test1", "test3", "result", Markers::Array));
-    s.add_edge(("test3", "test2", "result", Markers::Array));
-    s.add_edge(("test4", "test3", "result", Markers::Array));
-    s.add_edge(("test2", "test3", "result", Markers::Array));
+    s.add_edge(("test1", "test3", "result", Markers::Array))
+        .unwrap();
+    s.add_edge(("test3", "test2", "result", Markers::Array))
+        .unwrap();
+    s.add_edge(("test4", "test3", "result", Markers::Array))
+        .unwrap();
+    s.add_edge(("test2", "test3", "result", Markers::Array))
+        .unwrap();
 
     let svg = s.make_svg().change_background_color("#fff".into());
 
     svg::save("img/sequence.svg", &svg).unwrap();
 }
+
+#[test]
+fn test_sequence_activation() {
+    let mut s = Sequence::new(ThemeName::Default);
+    s.add_nodes(vec!["client", "server", "db"]);
+    s.add_edge(("client", "server", "request", Markers::Array))
+        .unwrap();
+    s.activate("server").unwrap();
+    s.add_edge(("server", "db", "query", Markers::Array))
+        .unwrap();
+    s.activate("db").unwrap();
+    s.add_edge(("db", "server", "rows", Markers::Array))
+        .unwrap();
+    s.deactivate("db").unwrap();
+    s.add_edge(("server", "server", "render", Markers::Array))
+        .unwrap();
+    s.activate("server").unwrap();
+    s.deactivate("server").unwrap();
+    s.add_edge(("server", "client", "response", Markers::Array))
+        .unwrap();
+    s.deactivate("server").unwrap();
+
+    let svg = s.make_svg().to_string();
+    // 参加者の箱 3 * 2 + activation 3
+    assert_eq!(svg.matches("<rect").count(), 9);
+    // 角の丸くない箱が activation。server の外側, db, server の内側の順に並ぶ
+    let bars: Vec<(usize, usize)> = elements(&svg, "<rect")
+        .into_iter()
+        .filter(|rect| !rect.contains(" rx="))
+        .map(|rect| {
+            (
+                attribute(rect, "x"),
+                attribute(rect, "x") + attribute(rect, "width"),
+            )
+        })
+        .collect();
+    let [server, db, nested] = bars[..] else {
+        panic!("{:?}", bars)
+    };
+    // メッセージの矢印は activation の端に接続する
+    let line = |label: &str| {
+        let line = element_before(&svg, "<line", label);
+        (attribute(line, "x1"), attribute(line, "x2"))
+    };
+    assert_eq!(line("request").1, server.0);
+    assert_eq!(line("query"), (server.1, db.0));
+    assert_eq!(line("rows"), (db.0, server.1));
+    assert_eq!(line("response").0, server.0);
+    // 入れ子の activation は外側から右にずらす
+    assert!(server.0 < nested.0 && server.1 < nested.1);
+    assert!(element_before(&svg, "<path", "render").contains(&format!("H{}\"", nested.1)));
+}
+
+#[test]
+fn test_sequence_self_message() {
+    let mut s = Sequence::new(ThemeName::Default);
+    s.add_nodes(vec!["a", "b"]);
+    s.add_edge(("a", "b", "call", Markers::Array)).unwrap();
+    s.add_edge(("b", "b", "recurse", Markers::Array)).unwrap();
+    s.add_edge(("b", "a", "done", Markers::Array)).unwrap();
+    let (_, _, _, height) = s.bounding_box();
+
+    let mut plain = Sequence::new(ThemeName::Default);
+    plain.add_nodes(vec!["a", "b"]);
+    plain.add_edge(("a", "b", "call", Markers::Array)).unwrap();
+    plain
+        .add_edge(("a", "b", "recurse", Markers::Array))
+        .unwrap();
+    plain.add_edge(("b", "a", "done", Markers::Array)).unwrap();
+    assert!(height > plain.bounding_box().3);
+
+    let svg = s.make_svg().to_string();
+    assert_eq!(svg.matches("<path").count(), 2); // marker + self message
+                                                 // b のライフラインから右に出て、下で同じライフラインに戻る
+    let call = element_before(&svg, "<line", "call");
+    let (b, call_y) = (attribute(call, "x2"), attribute(call, "y2"));
+    let done_y = attribute(element_before(&svg, "<line", "done"), "y1");
+    let points = path_numbers(element_before(&svg, "<path", "recurse"));
+    let [x1, y1, right, y2, x2] = points[..] else {
+        panic!("{:?}", points)
+    };
+    assert_eq!((x1, x2), (b, b));
+    assert!(right > b);
+    assert!(call_y < y1 && y1 < y2 && y2 < done_y);
+    // ラベルは輪の右に置く
+    assert!(label_x(&svg, "recurse") > right);
+}
+
+#[test]
+fn test_sequence_message_kinds() {
+    let mut s = Sequence::new(ThemeName::Default);
+    s.add_nodes(vec!["a", "b"]);
+    s.add_message(("a", "b", "async", MessageKind::Async))
+        .unwrap();
+    s.activate("b").unwrap();
+    s.add_message(("b", "a", "reply", MessageKind::Reply))
+        .unwrap();
+    s.add_message(("", "a", "found", MessageKind::Found))
+        .unwrap();
+    s.add_message(("b", "", "lost", MessageKind::Lost)).unwrap();
+
+    let svg = s.make_svg().to_string();
+    assert_eq!(svg.matches("stroke-dasharray").count(), 1);
+    // OpenArray, Array, Circle, ArrayCircle
+    assert_eq!(svg.matches("<marker").count(), 4);
+
+    let mut sync_only = Sequence::new(ThemeName::Default);
+    sync_only.add_nodes(vec!["a", "b"]);
+    sync_only
+        .add_message(("a", "b", "sync", MessageKind::Sync))
+        .unwrap();
+    let svg = sync_only.make_svg().to_string();
+    assert_eq!(svg.matches("<marker").count(), 1);
+}
+
+#[test]
+fn test_sequence_fragments() {
+    let mut s = Sequence::new(ThemeName::Default);
+    s.add_nodes(vec!["client", "server", "db"]);
+    s.begin_fragment(FragmentKind::Alt, "cached");
+    s.add_edge(("client", "server", "get", Markers::Array))
+        .unwrap();
+    s.else_fragment("miss").unwrap();
+    s.begin_fragment(FragmentKind::Loop, "retry");
+    s.add_edge(("server", "db", "query", Markers::Array))
+        .unwrap();
+    s.end_fragment().unwrap();
+    s.end_fragment().unwrap();
+    let (_, _, _, height) = s.bounding_box();
+
+    let mut plain = Sequence::new(ThemeName::Default);
+    plain.add_nodes(vec!["client", "server", "db"]);
+    plain
+        .add_edge(("client", "server", "get", Markers::Array))
+        .unwrap();
+    plain
+        .add_edge(("server", "db", "query", Markers::Array))
+        .unwrap();
+    assert!(height > plain.bounding_box().3);
+
+    let svg = s.make_svg().to_string();
+    assert_eq!(svg.matches("stroke-dasharray").count(), 1);
+    assert!(svg.contains("[cached]"));
+    assert!(svg.contains("[miss]"));
+    assert!(svg.contains("loop"));
+
+    // 2 つ目以降のオペランドの長い条件も枠に収める
+    let mut s = Sequence::new(ThemeName::Default);
+    s.add_nodes(vec!["a", "b"]);
+    s.begin_fragment(FragmentKind::Alt, "ok");
+    s.add_edge(("a", "b", "get", Markers::Array)).unwrap();
+    s.else_fragment("the cache is cold and must be rebuilt")
+        .unwrap();
+    s.add_edge(("a", "b", "load", Markers::Array)).unwrap();
+    s.end_fragment().unwrap();
+    let svg = s.make_svg().to_string();
+    let guard = "[the cache is cold and must be rebuilt]";
+    let frame = element_before(&svg, "<rect", guard);
+    let right = attribute(frame, "x") + attribute(frame, "width");
+    let text = text_element(&svg, guard);
+    let width = TextMetrics::new().width(guard, attribute(text, "font-size"));
+    assert!(attribute(text, "x") + width <= right);
+}
+
+#[test]
+fn test_sequence_notes() {
+    let mut s = Sequence::new(ThemeName::Default);
+    s.add_nodes(vec!["a", "b"]);
+    s.add_edge(("a", "b", "call", Markers::Array)).unwrap();
+    let (_, _, width, height) = s.bounding_box();
+    s.add_note(NotePosition::LeftOf("a"), "left side\nof a")
+        .unwrap();
+    s.add_note(NotePosition::RightOf("b"), "right").unwrap();
+    s.add_note(NotePosition::Over(&["a", "b"]), "over both")
+        .unwrap();
+    let (_, _, noted_width, noted_height) = s.bounding_box();
+    // 左右のノートの分だけ広がる
+    assert!(noted_width > width);
+    assert!(noted_height > height);
+
+    let svg = s.make_svg().to_string();
+    assert_eq!(svg.matches("<tspan").count(), 4);
+    // 最初の参加者の箱は左のノートの分だけ右にずれる
+    assert!(!svg.contains(r#"x="20" y="20""#));
+}
+
+fn ordered_sequence() -> Sequence {
+    let mut s = Sequence::new(ThemeName::Default);
+    s.add_nodes(vec!["alpha", "beta", "gamma", "delta"]);
+    s.add_edge(("alpha", "gamma", "call", Markers::Array))
+        .unwrap();
+    s.add_message(("gamma", "alpha", "reply", MessageKind::Reply))
+        .unwrap();
+    s.add_message(("delta", "beta", "async", MessageKind::Async))
+        .unwrap();
+    s
+}
+
+#[test]
+fn test_sequence_deterministic() {
+    let first = ordered_sequence().make_svg().to_string();
+    let second = ordered_sequence().make_svg().to_string();
+    assert_eq!(first, second);
+    // 追加した順に左から並ぶ
+    assert!(label_x(&first, "alpha") < label_x(&first, "beta"));
+    assert!(label_x(&first, "beta") < label_x(&first, "gamma"));
+    assert!(label_x(&first, "gamma") < label_x(&first, "delta"));
+}
+
+/// path 要素の d 属性に並ぶ数
+fn path_numbers(path: &str) -> Vec<usize> {
+    let d = path.split(" d=\"").nth(1).unwrap();
+    d[..d.find('"').unwrap()]
+        .split(|c: char| !c.is_ascii_digit())
+        .filter(|n| !n.is_empty())
+        .map(|n| n.parse().unwrap())
+        .collect()
+}
+
+/// 最初に現れる text 要素の x 座標
+fn label_x(svg: &str, label: &str) -> usize {
+    attribute(text_element(svg, label), "x")
+}
+
+#[test]
+fn test_sequence_reorder() {
+    let mut s = ordered_sequence();
+    let before = s.make_svg().to_string();
+    s.reorder_nodes(&["delta", "alpha"]).unwrap();
+    let after = s.make_svg().to_string();
+    assert_ne!(before, after);
+    assert!(label_x(&after, "delta") < label_x(&after, "alpha"));
+    assert!(label_x(&after, "alpha") < label_x(&after, "beta"));
+    assert!(label_x(&after, "beta") < label_x(&after, "gamma"));
+
+    let mut moved = ordered_sequence();
+    moved.move_node("delta", 0).unwrap();
+    moved.move_node("alpha", 1).unwrap();
+    assert_eq!(moved.make_svg().to_string(), after);
+}
+
+#[test]
+fn test_sequence_participant_shapes() {
+    let mut s = Sequence::new(ThemeName::Default);
+    s.add_nodes(vec!["a", "b"]);
+    s.add_edge(("a", "b", "call", Markers::Array)).unwrap();
+    let (_, _, _, height) = s.bounding_box();
+
+    let mut shaped = Sequence::new(ThemeName::Default);
+    shaped.add_participant("a", ParticipantKind::Actor);
+    shaped.add_participant("b", ParticipantKind::Database);
+    shaped.add_edge(("a", "b", "call", Markers::Array)).unwrap();
+    let (_, _, _, shaped_height) = shaped.bounding_box();
+    // アイコンの分だけ上下に高くなる
+    assert!(shaped_height > height);
+
+    let svg = shaped.make_svg().to_string();
+    // 棒人間の頭は上下に 1 つずつ
+    assert_eq!(svg.matches("<circle").count(), 2);
+    assert_eq!(svg.matches(">\na\n").count(), 2);
+    assert_eq!(svg.matches(">\nb\n").count(), 2);
+    let call = element_before(&svg, "<line", "call");
+    let (a, b) = (attribute(call, "x1"), attribute(call, "x2"));
+    let lifeline = |x: usize| {
+        elements(&svg, "<line")
+            .into_iter()
+            .find(|line| !line.contains("marker") && attribute(line, "x1") == x)
+            .unwrap()
+    };
+    // 上の棒人間は頭、名前、ライフラインの順に並び、下の棒人間はライフラインの下にある
+    let circles = elements(&svg, "<circle");
+    let (head, foot) = (circles[0], circles[1]);
+    let name_y = attribute(text_element(&svg, "a"), "y");
+    assert_eq!(attribute(head, "cx"), a);
+    assert!(attribute(head, "cy") + attribute(head, "r") < name_y);
+    assert!(name_y < attribute(lifeline(a), "y1"));
+    assert!(attribute(foot, "cy") > attribute(lifeline(a), "y2"));
+    // 円柱はライフラインを中心に置き、ライフラインは底の縁から伸びる
+    let cylinder = path_numbers(element_before(&svg, "<path", "b"));
+    let (left, right, ry, bottom) = (cylinder[0], cylinder[7], cylinder[3], cylinder[16]);
+    assert_eq!((left + right) / 2, b);
+    let top = attribute(lifeline(b), "y1");
+    assert!(bottom <= top && top <= bottom + ry);
+}
+
+#[test]
+fn test_sequence_create_destroy() {
+    let mut s = Sequence::new(ThemeName::Default);
+    s.add_nodes(vec!["a", "b"]);
+    s.add_edge(("a", "b", "call", Markers::Array)).unwrap();
+    s.add_node("c");
+    s.add_message(("b", "c", "new", MessageKind::Create))
+        .unwrap();
+    s.add_edge(("b", "c", "work", Markers::Array)).unwrap();
+    s.activate("c").unwrap();
+    s.add_edge(("a", "b", "done", Markers::Array)).unwrap();
+    s.destroy("c").unwrap();
+    s.add_edge(("a", "b", "after", Markers::Array)).unwrap();
+
+    let svg = s.make_svg().to_string();
+    // 破棄された参加者は下側に図形を描かず、× を描く
+    assert_eq!(svg.matches(">\na\n").count(), 2);
+    assert_eq!(svg.matches(">\nc\n").count(), 1);
+    assert_eq!(svg.matches(r#"stroke-width="2""#).count(), 1);
+    // 生成された参加者の頭は他の参加者より下にある
+    let head_y = |label: &str| attribute(text_element(&svg, label), "y");
+    assert!(head_y("c") > head_y("a") + 30);
+}
+
+#[test]
+fn test_sequence_activate_destroyed() {
+    let mut s = Sequence::new(ThemeName::Default);
+    s.add_nodes(vec!["a", "b"]);
+    s.add_edge(("a", "b", "call", Markers::Array)).unwrap();
+    s.destroy("b").unwrap();
+    s.add_edge(("a", "a", "self", Markers::Array)).unwrap();
+    s.add_edge(("a", "a", "again", Markers::Array)).unwrap();
+    // 破棄した後のライフラインには activation を置けない
+    let destroyed = SequenceError::Destroyed("b".to_string());
+    assert_eq!(s.activate("b").err(), Some(destroyed.clone()));
+    assert_eq!(s.deactivate("b").err(), Some(destroyed));
+    s.make_svg();
+}
+
+#[test]
+fn test_sequence_messages_outside_lifeline() {
+    let mut s = Sequence::new(ThemeName::Default);
+    s.add_nodes(vec!["a", "b"]);
+    s.add_edge(("a", "b", "call", Markers::Array)).unwrap();
+    s.destroy("b").unwrap();
+    // 破棄した参加者とはメッセージをやり取りできない
+    let destroyed = Some(SequenceError::Destroyed("b".to_string()));
+    assert_eq!(
+        s.add_edge(("a", "b", "late", Markers::Array)).err(),
+        destroyed
+    );
+    assert_eq!(
+        s.add_edge(("b", "a", "late", Markers::Array)).err(),
+        destroyed
+    );
+    assert_eq!(
+        s.add_message(("a", "b", "late", MessageKind::Async)).err(),
+        destroyed
+    );
+    assert_eq!(
+        s.add_message(("b", "", "late", MessageKind::Lost)).err(),
+        destroyed
+    );
+    assert_eq!(
+        s.add_message(("", "b", "late", MessageKind::Found)).err(),
+        destroyed
+    );
+    assert_eq!(
+        s.add_incoming((Gate::Left, "b", "late", MessageKind::Sync))
+            .err(),
+        destroyed
+    );
+    assert_eq!(
+        s.add_outgoing(("b", Gate::Right, "late", MessageKind::Reply))
+            .err(),
+        destroyed
+    );
+
+    // 生成メッセージより前にメッセージを受けた参加者は生成できない
+    s.add_node("c");
+    s.add_edge(("a", "c", "early", Markers::Array)).unwrap();
+    assert_eq!(
+        s.add_message(("a", "c", "new", MessageKind::Create)).err(),
+        Some(SequenceError::UsedBeforeCreate("c".to_string()))
+    );
+    s.add_node("d");
+    s.add_message(("a", "d", "new", MessageKind::Create))
+        .unwrap();
+    s.add_edge(("d", "a", "ready", Markers::Array)).unwrap();
+    s.make_svg();
+}
+
+#[test]
+fn test_sequence_autonumber() {
+    let mut s = Sequence::new(ThemeName::Default);
+    s.add_nodes(vec!["a", "b"]);
+    s.add_edge(("a", "b", "plain", Markers::Array)).unwrap();
+    s.autonumber(1, 1);
+    s.autonumber_hierarchical(true);
+    s.add_edge(("a", "b", "first", Markers::Array)).unwrap();
+    s.begin_fragment(FragmentKind::Loop, "retry");
+    s.add_edge(("a", "b", "inner", Markers::Array)).unwrap();
+    s.add_message(("b", "a", "ack", MessageKind::Reply))
+        .unwrap();
+    s.end_fragment().unwrap();
+    s.stop_autonumber();
+    s.add_edge(("a", "b", "silent", Markers::Array)).unwrap();
+    s.resume_autonumber();
+    s.autonumber_format("[{}]");
+    s.add_edge(("a", "b", "last", Markers::Array)).unwrap();
+
+    let svg = s.make_svg().to_string();
+    assert!(svg.contains(">\nplain\n"));
+    assert!(svg.contains(">\n1 first\n"));
+    assert!(svg.contains(">\n2.1 inner\n"));
+    assert!(svg.contains(">\n2.2 ack\n"));
+    assert!(svg.contains(">\nsilent\n"));
+    assert!(svg.contains(">\n[3] last\n"));
+}
+
+#[test]
+fn test_sequence_groups() {
+    let mut s = Sequence::new(ThemeName::Default);
+    s.add_nodes(vec!["api", "client", "db", "cache"]);
+    s.add_edge(("client", "api", "request", Markers::Array))
+        .unwrap();
+    s.add_edge(("api", "db", "query", Markers::Array)).unwrap();
+    let (_, _, _, height) = s.bounding_box();
+    s.add_group("Backend", &["api", "db", "cache"], "#e0f0ff")
+        .unwrap();
+    let (_, _, _, grouped_height) = s.bounding_box();
+    // タイトルの分だけ高くなる
+    assert!(grouped_height > height);
+
+    let svg = s.make_svg().to_string();
+    assert!(svg.contains(r##"fill="#e0f0ff""##));
+    assert!(svg.contains(">\nBackend\n"));
+    // 箱はメンバーの上下の図形をすべて囲み、タイトルはその上に置く
+    let group = element_before(&svg, "<rect", "Backend");
+    let (left, top) = (attribute(group, "x"), attribute(group, "y"));
+    let right = left + attribute(group, "width");
+    let bottom = top + attribute(group, "height");
+    let boxes = |name: &str| -> Vec<(usize, usize, usize, usize)> {
+        svg.match_indices(&format!(">\n{}\n", name))
+            .map(|(end, _)| {
+                let start = svg[..end].rfind("<rect").unwrap();
+                let rect = &svg[start..end];
+                (
+                    attribute(rect, "x"),
+                    attribute(rect, "y"),
+                    attribute(rect, "x") + attribute(rect, "width"),
+                    attribute(rect, "y") + attribute(rect, "height"),
+                )
+            })
+            .collect()
+    };
+    let title_y = attribute(text_element(&svg, "Backend"), "y");
+    for name in ["api", "db", "cache"] {
+        for (x1, y1, x2, y2) in boxes(name) {
+            assert!(
+                left < x1 && x2 < right && top < y1 && y2 < bottom,
+                "{}",
+                name
+            );
+            assert!(title_y < y1, "{}", name);
+        }
+    }
+    for (x1, _, _, _) in boxes("client") {
+        assert!(right < x1);
+    }
+    // グループのメンバーは隣り合い、client はその右に移る
+    assert!(label_x(&svg, "api") < label_x(&svg, "db"));
+    assert!(label_x(&svg, "db") < label_x(&svg, "cache"));
+    assert!(label_x(&svg, "cache") < label_x(&svg, "client"));
+
+    // グループの途中に移動してもメンバーは離れない
+    s.move_node("client", 1).unwrap();
+    let svg = s.make_svg().to_string();
+    assert!(label_x(&svg, "cache") < label_x(&svg, "client"));
+    s.move_node("client", 0).unwrap();
+    let svg = s.make_svg().to_string();
+    assert!(label_x(&svg, "client") < label_x(&svg, "api"));
+    assert!(label_x(&svg, "api") < label_x(&svg, "db"));
+}
+
+#[test]
+fn test_sequence_dividers() {
+    let mut s = Sequence::new(ThemeName::Default);
+    s.add_nodes(vec!["a", "b"]);
+    s.add_edge(("a", "b", "call", Markers::Array)).unwrap();
+    s.add_divider("Authentication");
+    s.add_edge(("a", "b", "login", Markers::Array)).unwrap();
+    s.add_delay("5 minutes later");
+    s.add_space(40);
+    s.add_edge(("a", "b", "done", Markers::Array)).unwrap();
+    let (_, _, width, _) = s.bounding_box();
+    // 区切りのラベルが収まる幅になる
+    assert!(width >= "Authentication".len() * 8);
+
+    let svg = s.make_svg().to_string();
+    let message = |label: &str| element_before(&svg, "<line", label);
+    let (a, b) = (
+        attribute(message("call"), "x1"),
+        attribute(message("call"), "x2"),
+    );
+    let y = |label: &str| attribute(message(label), "y1");
+    // 区切り線はすべてのライフラインを横切り、前後のメッセージの間にある
+    let divider = element_before(&svg, "<line", "Authentication");
+    assert!(attribute(divider, "x1") < a && b < attribute(divider, "x2"));
+    assert!(y("call") < attribute(divider, "y1") && attribute(divider, "y1") < y("login"));
+    let label = element_before(&svg, "<rect", "Authentication");
+    assert_eq!(
+        attribute(label, "x") + attribute(label, "width") / 2,
+        label_x(&svg, "Authentication")
+    );
+    // 経過の間はライフラインごとに点線になり、ラベルはその途中に置く
+    let dashed: Vec<&str> = elements(&svg, "<line")
+        .into_iter()
+        .filter(|line| line.contains(r#"stroke-dasharray="2""#))
+        .collect();
+    assert_eq!(dashed.len(), 2);
+    let delay_y = attribute(text_element(&svg, "5 minutes later"), "y");
+    for line in dashed {
+        assert!(attribute(line, "y1") < delay_y && delay_y < attribute(line, "y2"));
+        assert!(y("login") <= attribute(line, "y1"));
+        // 空白の分だけ次のメッセージとの間があく
+        assert!(attribute(line, "y2") + 40 <= y("done"));
+    }
+}
+
+#[test]
+fn test_sequence_multiline_labels() {
+    let mut s = Sequence::new(ThemeName::Default);
+    s.add_nodes(vec!["a", "b"]);
+    s.add_edge(("a", "b", "call", Markers::Array)).unwrap();
+    s.add_edge(("b", "a", "back", Markers::Array)).unwrap();
+    let (_, _, _, height) = s.bounding_box();
+
+    let mut multi = Sequence::new(ThemeName::Default);
+    multi.add_nodes(vec!["a", "b"]);
+    multi
+        .add_edge(("a", "b", "first line\nsecond line", Markers::Array))
+        .unwrap();
+    multi
+        .add_edge(("b", "a", "a rather long label that wraps", Markers::Array))
+        .unwrap();
+    multi.wrap_message(80).unwrap();
+    let (_, _, _, multi_height) = multi.bounding_box();
+    // 行数の分だけ行が高くなる
+    assert!(multi_height > height);
+
+    let svg = multi.make_svg().to_string();
+    assert_eq!(svg.matches("<tspan").count(), 2 + 3);
+    assert!(svg.contains(">\nsecond line\n"));
+    assert!(svg.contains(">\nlong label\n"));
+    // 行ごとの tspan を重ならないように、それぞれの横線の上に積む
+    let lines = |label: &str| -> (usize, usize, Vec<usize>) {
+        let end = svg.find(&format!(">\n{}\n", label)).unwrap();
+        let start = svg[..end].rfind("<text").unwrap();
+        let text = &svg[start..start + svg[start..].find("</text>").unwrap()];
+        let dys = elements(text, "<tspan")
+            .into_iter()
+            .map(|tspan| attribute(tspan, "dy"))
+            .collect();
+        (attribute(text, "y"), attribute(text, "font-size"), dys)
+    };
+    let arrow_y = |label: &str| attribute(element_before(&svg, "<line", label), "y1");
+    let mut above = 0;
+    for (label, count) in [("first line", 2), ("a rather", 3)] {
+        let (y, font_size, dys) = lines(label);
+        assert_eq!(dys.len(), count);
+        assert!(dys[1..].iter().all(|&dy| dy >= font_size));
+        assert!(above < y - font_size);
+        assert!(y + dys.iter().sum::<usize>() < arrow_y(label));
+        above = arrow_y(label);
+    }
+    // 折り返した行は指定した幅に収まる
+    let (_, font_size, _) = lines("a rather");
+    for line in ["a rather", "long label", "that wraps"] {
+        assert!(TextMetrics::new().width(line, font_size) <= 80);
+    }
+}
+
+#[test]
+fn test_sequence_errors() {
+    let mut s = Sequence::new(ThemeName::Default);
+    s.add_nodes(vec!["a", "b"]);
+    let unknown = SequenceError::UnknownParticipant("x".to_string());
+    assert_eq!(
+        s.add_edge(("a", "x", "call", Markers::Array)).err(),
+        Some(unknown.clone())
+    );
+    assert_eq!(
+        s.add_message(("x", "b", "call", MessageKind::Async)).err(),
+        Some(unknown.clone())
+    );
+    assert_eq!(
+        s.add_note(NotePosition::Over(&[]), "note").err(),
+        Some(SequenceError::NoParticipants)
+    );
+    assert_eq!(s.wrap_message(40).err(), Some(SequenceError::NoMessage));
+    assert_eq!(s.destroy("x").err(), Some(unknown.clone()));
+    assert_eq!(s.activate("x").err(), Some(unknown.clone()));
+    assert_eq!(s.deactivate("x").err(), Some(unknown.clone()));
+    assert_eq!(
+        s.deactivate("a").err(),
+        Some(SequenceError::NotActivated("a".to_string()))
+    );
+    assert_eq!(s.reorder_nodes(&["b", "x"]).err(), Some(unknown.clone()));
+    assert_eq!(s.move_node("x", 0).err(), Some(unknown));
+    assert_eq!(
+        s.else_fragment("else").err(),
+        Some(SequenceError::NoOpenFragment)
+    );
+    assert_eq!(s.end_fragment().err(), Some(SequenceError::NoOpenFragment));
+    s.add_group("g", &["a"], "#ffffff").unwrap();
+    assert_eq!(
+        s.add_group("h", &["b", "a"], "#ffffff").err(),
+        Some(SequenceError::AlreadyGrouped("a".to_string()))
+    );
+    assert_eq!(
+        SequenceError::UnknownParticipant("x".to_string()).to_string(),
+        "unknown participant: x"
+    );
+}
+
+#[test]
+fn test_sequence_ref_and_gates() {
+    let mut s = Sequence::new(ThemeName::Default);
+    s.add_nodes(vec!["a", "b", "c"]);
+    s.add_incoming((Gate::Left, "a", "start", MessageKind::Sync))
+        .unwrap();
+    s.add_ref(&["a", "b"], "Login flow").unwrap();
+    s.add_edge(("b", "c", "query", Markers::Array)).unwrap();
+    s.add_outgoing(("c", Gate::Right, "notify", MessageKind::Async))
+        .unwrap();
+    assert_eq!(
+        s.add_ref(&["a", "z"], "missing").err(),
+        Some(SequenceError::UnknownParticipant("z".to_string()))
+    );
+
+    let (_, _, width, height) = s.bounding_box();
+    let svg = s.make_svg().to_string();
+    assert!(svg.contains(">\nref\n"));
+    assert!(svg.contains(">\nLogin flow\n"));
+    // gate のメッセージは図の枠の端に接続する
+    assert!(svg.contains(&format!(r#"x1="{}""#, 20 / 2)));
+    assert!(svg.contains(&format!(r#"x2="{}""#, width - 20 / 2)));
+    // 図の枠
+    assert!(svg.contains(&format!(
+        r##"height="{}" stroke="#000000" stroke-width="1" width="{}" x="10" y="10""##,
+        height - 20,
+        width - 20
+    )));
+}
+
+#[test]
+fn test_sequence_unicode_width() {
+    let sequence = |names: Vec<&str>, label: &str| {
+        let mut s = Sequence::new(ThemeName::Default);
+        s.add_nodes(names.clone());
+        s.add_edge((names[0], names[1], label, Markers::Array))
+            .unwrap();
+        s.add_note(NotePosition::RightOf(names[1]), label).unwrap();
+        s.bounding_box()
+    };
+    // 全角文字は半角 2 文字分の幅で数える
+    assert_eq!(
+        sequence(vec!["サーバ", "DB"], "問い合わせ"),
+        sequence(vec!["server", "DB"], "0123456789")
+    );
+    // 結合文字や ZWJ でつないだ絵文字は 1 文字分
+    assert_eq!(
+        sequence(vec!["cafe\u{301}", "👩\u{200d}💻"], "e\u{301}"),
+        sequence(vec!["cafe", "ab"], "e")
+    );
+}
+
+#[test]
+fn test_sequence_font_metrics() {
+    // 同時に走る別のテストプロセスと重ならない名前にする
+    let family = format!("uml-sequence-test-font-{}", std::process::id());
+    let path = std::env::temp_dir().join(format!("{}.ttf", family));
+    std::fs::write(&path, make_test_font()).unwrap();
+    let sequence = |metrics: Option<TextMetrics>| {
+        let mut s = Sequence::new(ThemeName::Default);
+        if let Some(metrics) = metrics {
+            s.set_metrics(metrics);
+        }
+        s.add_nodes(vec!["AVAVAVAV", "VA"]);
+        s.add_edge(("AVAVAVAV", "VA", "AV", Markers::Array))
+            .unwrap();
+        s
+    };
+    let (_, _, width, _) = sequence(None).bounding_box();
+    let metrics = TextMetrics::load(&[&path]);
+    std::fs::remove_file(&path).unwrap();
+    let mut s = sequence(Some(metrics.unwrap()));
+    // 実際のグリフ幅で測ると半角 1 文字 = FONT_SIZE より狭い
+    assert!(s.bounding_box().2 < width);
+    let svg = s.make_svg().to_string();
+    assert!(svg.contains(&format!("font-family=\"'{}'\"", family)));
+    assert!(TextMetrics::load(&[std::env::temp_dir().join("no-such-font.ttf")]).is_err());
+}
+
+#[test]
+fn test_sequence_participant_widths() {
+    let mut s = Sequence::new(ThemeName::Default);
+    s.add_nodes(vec!["a", "b", "a very long participant", "c", "d"]);
+    s.add_edge(("c", "d", "a rather long message label", Markers::Array))
+        .unwrap();
+    let svg = s.make_svg().to_string();
+    let gap = |left: &str, right: &str| label_x(&svg, right) - label_x(&svg, left);
+    // 長い名前の参加者がいても他の間隔は広がらない
+    assert!(gap("a", "b") < 40);
+    assert!(gap("b", "a very long participant") > gap("a", "b"));
+    // ラベルの分だけ間隔が広がる
+    assert!(gap("c", "d") >= "a rather long message label".len() * 8);
+    // 参加者ごとに箱の幅が違う
+    assert!(svg.contains(r#"width="14""#));
+    assert!(svg.contains(&format!(r#"width="{}""#, 23 * 8 + 6)));
+}
@@ -0,0 +1,197 @@
+use crate::helper::*;
+
+/// クラス間の関係の種類
+///
+/// 関係は `from` から `to` へ向かう。汎化・実現・依存・関連の矢印は `to` 側に、
+/// コンポジション・集約のひし形は全体である `from` 側に付く。
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Relation {
+    /// 汎化 (実線 + 白抜きの三角形)。`to` が親クラス
+    Inheritance,
+    /// 実現 (破線 + 白抜きの三角形)。`to` がインターフェース
+    Realization,
+    /// コンポジション (実線 + 黒塗りのひし形)
+    Composition,
+    /// 集約 (実線 + 白抜きのひし形)
+    Aggregation,
+    /// 関連 (実線)。navigable なら `to` 側に矢印を付ける
+    Association { navigable: bool },
+    /// 依存 (破線 + 開いた矢印)
+    Dependency,
+}
+
+impl Relation {
+    pub fn marker_end(&self) -> Markers {
+        match self {
+            Relation::Inheritance | Relation::Realization => Markers::Triangle,
+            Relation::Association { navigable: true } | Relation::Dependency => Markers::OpenArray,
+            _ => Markers::None,
+        }
+    }
+
+    pub fn marker_start(&self) -> Markers {
+        match self {
+            Relation::Composition => Markers::Diamond,
+            Relation::Aggregation => Markers::OpenDiamond,
+            _ => Markers::None,
+        }
+    }
+
+    #[inline]
+    pub fn is_dashed(&self) -> bool {
+        matches!(self, Relation::Realization | Relation::Dependency)
+    }
+
+    /// 親クラス・インターフェースが上に来るように、階層を決めるときは向きを逆にする
+    #[inline]
+    pub fn is_upward(&self) -> bool {
+        matches!(self, Relation::Inheritance | Relation::Realization)
+    }
+}
+
+/// 関係の片方の端に付ける多重度とロール名
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct EdgeEnd {
+    pub multiplicity: Option<String>,
+    pub role: Option<String>,
+}
+
+impl EdgeEnd {
+    /// 空文字列は表示しない
+    pub fn new(multiplicity: &str, role: &str) -> Self {
+        let text = |s: &str| (!s.is_empty()).then(|| s.to_string());
+        EdgeEnd {
+            multiplicity: text(multiplicity),
+            role: text(role),
+        }
+    }
+}
+
+/// クラス間の関係。from / to は 1-indexed のクラスの番号
+#[derive(PartialEq, Debug, Clone)]
+pub struct ClassEdge {
+    from: usize,
+    to: usize,
+    kind: Relation,
+    from_end: EdgeEnd,
+    to_end: EdgeEnd,
+    label: Option<String>,
+}
+
+impl ClassEdge {
+    pub fn new(from: usize, to: usize, kind: Relation) -> Self {
+        ClassEdge {
+            from,
+            to,
+            kind,
+            from_end: EdgeEnd::default(),
+            to_end: EdgeEnd::default(),
+            label: None,
+        }
+    }
+
+    /// 線の中央に付けるラベル
+    pub fn set_label(&mut self, label: &str) -> &mut Self {
+        self.label = Some(label.to_string());
+        self
+    }
+
+    /// from 側の端の多重度とロール名
+    pub fn set_from_end(&mut self, multiplicity: &str, role: &str) -> &mut Self {
+        self.from_end = EdgeEnd::new(multiplicity, role);
+        self
+    }
+
+    /// to 側の端の多重度とロール名
+    pub fn set_to_end(&mut self, multiplicity: &str, role: &str) -> &mut Self {
+        self.to_end = EdgeEnd::new(multiplicity, role);
+        self
+    }
+
+    #[inline]
+    pub fn from(&self) -> usize {
+        self.from
+    }
+
+    #[inline]
+    pub fn to(&self) -> usize {
+        self.to
+    }
+
+    #[inline]
+    pub fn kind(&self) -> Relation {
+        self.kind
+    }
+
+    #[inline]
+    pub fn from_end(&self) -> &EdgeEnd {
+        &self.from_end
+    }
+
+    #[inline]
+    pub fn to_end(&self) -> &EdgeEnd {
+        &self.to_end
+    }
+
+    #[inline]
+    pub fn label(&self) -> Option<&str> {
+        self.label.as_deref()
+    }
+
+    /// 線の周りに文字を置くかどうか
+    pub fn has_labels(&self) -> bool {
+        self.label.is_some()
+            || self.from_end != EdgeEnd::default()
+            || self.to_end != EdgeEnd::default()
+    }
+
+    /// 端のクラスの番号だけを変えた複製
+    pub fn remap(&self, from: usize, to: usize) -> Self {
+        ClassEdge {
+            from,
+            to,
+            ..self.clone()
+        }
+    }
+
+    /// 階層を決めるための (上, 下) の組
+    pub fn rank_pair(&self) -> (usize, usize) {
+        if self.kind.is_upward() {
+            (self.to, self.from)
+        } else {
+            (self.from, self.to)
+        }
+    }
+}
+
+#[test]
+fn relation_markers() {
+    let inheritance = ClassEdge::new(2, 1, Relation::Inheritance);
+    assert_eq!(inheritance.kind().marker_end(), Markers::Triangle);
+    assert_eq!(inheritance.rank_pair(), (1, 2));
+    assert!(Relation::Realization.is_dashed());
+    assert_eq!(Relation::Composition.marker_start(), Markers::Diamond);
+    assert_eq!(Relation::Aggregation.marker_start(), Markers::OpenDiamond);
+    let plain = Relation::Association { navigable: false };
+    assert_eq!(plain.marker_end(), Markers::None);
+    assert_eq!(
+        Relation::Association { navigable: true }.marker_end(),
+        Markers::OpenArray
+    );
+    assert_eq!(ClassEdge::new(1, 2, plain).rank_pair(), (1, 2));
+}
+
+#[test]
+fn edge_end_labels() {
+    let mut edge = ClassEdge::new(1, 2, Relation::Composition);
+    assert!(!edge.has_labels());
+    edge.set_from_end("1", "").set_to_end("0..*", "items");
+    assert!(edge.has_labels());
+    assert_eq!(edge.from_end().multiplicity.as_deref(), Some("1"));
+    assert_eq!(edge.from_end().role, None);
+    assert_eq!(edge.to_end().role.as_deref(), Some("items"));
+    assert_eq!(edge.label(), None);
+    assert!(ClassEdge::new(1, 2, Relation::Dependency)
+        .set_label("uses")
+        .has_labels());
+}
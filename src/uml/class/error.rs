@@ -0,0 +1,29 @@
+use std::fmt;
+
+/// クラス図を組み立てるときのエラー
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum ClassError {
+    /// 登録されていないクラスの名前
+    UnknownClass(String),
+    /// 範囲外のクラスの番号 (1-indexed)
+    InvalidIndex(usize),
+    /// この図のものではないパッケージ
+    InvalidPackage(usize),
+    /// すでに他のパッケージに属しているクラス
+    AlreadyPackaged(String),
+}
+
+impl fmt::Display for ClassError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClassError::UnknownClass(name) => write!(f, "unknown class: {}", name),
+            ClassError::InvalidIndex(index) => write!(f, "class index out of range: {}", index),
+            ClassError::InvalidPackage(index) => write!(f, "unknown package: {}", index),
+            ClassError::AlreadyPackaged(name) => {
+                write!(f, "class is already in a package: {}", name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ClassError {}
@@ -0,0 +1,35 @@
+/// add_class が返すクラスのハンドル
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
+pub struct ClassId(pub(super) usize);
+
+/// 関係やノートを付けるクラスの指定方法
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum ClassKey<'a> {
+    Id(ClassId),
+    /// クラス名。同じ名前が複数あれば最初に追加したもの
+    Name(&'a str),
+    /// add_class を呼んだ順の 1-indexed の番号
+    Index(usize),
+}
+
+impl From<ClassId> for ClassKey<'_> {
+    fn from(id: ClassId) -> Self {
+        ClassKey::Id(id)
+    }
+}
+
+impl<'a> From<&'a str> for ClassKey<'a> {
+    fn from(name: &'a str) -> Self {
+        ClassKey::Name(name)
+    }
+}
+
+impl From<usize> for ClassKey<'_> {
+    fn from(index: usize) -> Self {
+        ClassKey::Index(index)
+    }
+}
+
+/// add_package が返すパッケージのハンドル
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
+pub struct PackageId(pub(super) usize);
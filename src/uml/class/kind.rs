@@ -0,0 +1,67 @@
+/// 分類子の種類
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum ClassKind {
+    Class,
+    Interface,
+    /// 抽象クラス。名前を斜体にする
+    Abstract,
+    /// 列挙型。リテラルの区画を持つ
+    Enum,
+    Record,
+    Struct,
+    Trait,
+}
+
+impl ClassKind {
+    /// 名前の上に表示するステレオタイプ。普通のクラスにはない
+    pub fn stereotype(&self) -> Option<&'static str> {
+        match self {
+            ClassKind::Class => None,
+            ClassKind::Interface => Some("interface"),
+            ClassKind::Abstract => Some("abstract"),
+            ClassKind::Enum => Some("enumeration"),
+            ClassKind::Record => Some("record"),
+            ClassKind::Struct => Some("struct"),
+            ClassKind::Trait => Some("trait"),
+        }
+    }
+
+    /// 丸で囲んで名前の左に表示する文字
+    pub fn letter(&self) -> &'static str {
+        match self {
+            ClassKind::Class => "C",
+            ClassKind::Interface => "I",
+            ClassKind::Abstract => "A",
+            ClassKind::Enum => "E",
+            ClassKind::Record => "R",
+            ClassKind::Struct => "S",
+            ClassKind::Trait => "T",
+        }
+    }
+
+    /// 丸の塗りの色
+    pub fn badge_color(&self) -> &'static str {
+        match self {
+            ClassKind::Class => "#add1b2",
+            ClassKind::Interface => "#b4a7e5",
+            ClassKind::Abstract => "#a9dcdf",
+            ClassKind::Enum => "#eb937f",
+            ClassKind::Record | ClassKind::Struct => "#f1d88b",
+            ClassKind::Trait => "#9fc5e8",
+        }
+    }
+
+    #[inline]
+    pub fn is_italic(&self) -> bool {
+        *self == ClassKind::Abstract
+    }
+}
+
+#[test]
+fn class_kinds() {
+    assert_eq!(ClassKind::Class.stereotype(), None);
+    assert_eq!(ClassKind::Interface.stereotype(), Some("interface"));
+    assert_eq!(ClassKind::Enum.letter(), "E");
+    assert!(ClassKind::Abstract.is_italic());
+    assert!(!ClassKind::Interface.is_italic());
+}
@@ -0,0 +1,482 @@
+use super::*;
+use std::collections::HashMap;
+
+/// パッケージの枠の内側の余白
+const FRAME_PADDING: usize = 10;
+/// パッケージの名前を書くタブの高さ
+const TAB_HEIGHT: usize = FONT_SIZE + PADDING * 2;
+/// 自分自身への関係の輪の大きさ
+const LOOP_SIZE: usize = MARGIN * 2;
+
+/// パッケージ (または図全体) の中を縦に区切った帯。
+/// 直下のクラスを並べる帯と、子のパッケージの帯を左から順に置く
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+enum Band {
+    Direct,
+    Package(usize),
+}
+
+/// クラスとパッケージの配置
+pub struct Layout {
+    /// 各クラスの箱の左上 (1-indexed)
+    pub positions: Vec<(usize, usize)>,
+    /// 全体の大きさ
+    pub size: (usize, usize),
+    /// パッケージごとの枠の (左, 上, 右, 下)。クラスを含まないパッケージは None
+    pub frames: Vec<Option<(usize, usize, usize, usize)>>,
+    /// パッケージ (None は図全体) の直下のクラスの帯の x の範囲
+    direct_bands: HashMap<Option<usize>, (usize, usize)>,
+    /// パッケージ (None は図全体) の中で、子の枠より上を横に通る線の y
+    corridors: HashMap<Option<usize>, usize>,
+}
+
+impl ClassUML {
+    /// class (1-indexed) を直下に持つパッケージ
+    pub(super) fn package_of(&self, class: usize) -> Option<usize> {
+        self.packages
+            .iter()
+            .position(|package| package.members().contains(&class))
+    }
+
+    /// 最上位から class を直下に持つパッケージまでの列
+    pub(super) fn package_path(&self, class: usize) -> Vec<usize> {
+        let mut path = vec![];
+        let mut package = self.package_of(class);
+        while let Some(index) = package {
+            path.push(index);
+            package = self.packages[index].parent();
+        }
+        path.reverse();
+        path
+    }
+
+    /// 入れ子の中も含めてクラスを持つかどうか
+    fn has_classes(&self, package: usize) -> bool {
+        !self.packages[package].members().is_empty()
+            || self
+                .packages
+                .iter()
+                .enumerate()
+                .any(|(child, p)| p.parent() == Some(package) && self.has_classes(child))
+    }
+
+    /// クラスを持つパッケージの入れ子の最大の深さ
+    fn package_depth(&self) -> usize {
+        (1..=self.nodes.len())
+            .map(|class| self.package_path(class).len())
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// container (None は図全体) の中に左から並べる帯
+    fn bands(&self, container: Option<usize>) -> Vec<Band> {
+        let direct = (1..=self.nodes.len()).any(|class| self.package_of(class) == container);
+        let children = self
+            .packages
+            .iter()
+            .enumerate()
+            .filter(|&(index, package)| package.parent() == container && self.has_classes(index))
+            .map(|(index, _)| Band::Package(index));
+        direct
+            .then_some(Band::Direct)
+            .into_iter()
+            .chain(children)
+            .collect()
+    }
+
+    /// container の直下のクラスを階層ごとに横に並べたときの最大の幅
+    fn direct_width(&self, container: Option<usize>, ranks: &[usize]) -> usize {
+        let mut widths = HashMap::new();
+        for (class, &rank) in ranks.iter().enumerate().skip(1) {
+            if self.package_of(class) == container {
+                *widths.entry(rank).or_insert(0) += self.footprint(class).0 + NODE_GAP;
+            }
+        }
+        widths
+            .values()
+            .copied()
+            .max()
+            .unwrap_or(0)
+            .saturating_sub(NODE_GAP)
+    }
+
+    fn band_width(&self, container: Option<usize>, band: Band, ranks: &[usize]) -> usize {
+        match band {
+            Band::Direct => self.direct_width(container, ranks),
+            Band::Package(package) => (self.inner_width(Some(package), ranks) + FRAME_PADDING * 2)
+                .max(self.tab_width(package)),
+        }
+    }
+
+    /// container の中の帯を隙間を空けて並べた幅
+    fn inner_width(&self, container: Option<usize>, ranks: &[usize]) -> usize {
+        self.bands(container)
+            .into_iter()
+            .map(|band| self.band_width(container, band, ranks) + NODE_GAP)
+            .sum::<usize>()
+            .saturating_sub(NODE_GAP)
+    }
+
+    /// パッケージの名前を書くタブの幅
+    fn tab_width(&self, package: usize) -> usize {
+        self.metrics.width(self.packages[package].name(), FONT_SIZE) + PADDING * 2
+    }
+
+    /// container の中の帯を x から並べ、クラスの x 座標と帯の範囲を決める
+    fn place(
+        &self,
+        container: Option<usize>,
+        mut x: usize,
+        ranks: &[usize],
+        xs: &mut [usize],
+        layout: &mut Layout,
+    ) {
+        for band in self.bands(container) {
+            let width = self.band_width(container, band, ranks);
+            match band {
+                Band::Direct => {
+                    let mut cursor = HashMap::new();
+                    for (class, &rank) in ranks.iter().enumerate().skip(1) {
+                        if self.package_of(class) == container {
+                            let cursor = cursor.entry(rank).or_insert(x);
+                            xs[class] = *cursor;
+                            *cursor += self.footprint(class).0 + NODE_GAP;
+                        }
+                    }
+                    layout.direct_bands.insert(container, (x, x + width));
+                }
+                Band::Package(package) => {
+                    self.place(Some(package), x + FRAME_PADDING, ranks, xs, layout);
+                    layout.frames[package] = Some((x, 0, x + width, 0));
+                }
+            }
+            x += width + NODE_GAP;
+        }
+    }
+
+    /// パッケージの枠の上下を決め、(上, 下) を返す
+    fn place_frame(&self, package: usize, layout: &mut Layout) -> (usize, usize) {
+        let mut top = usize::MAX;
+        let mut bottom = 0;
+        for &class in self.packages[package].members() {
+            let y = layout.positions[class].1;
+            top = top.min(y - self.template_overhang(class).1);
+            bottom = bottom.max(y + self.footprint(class).1);
+        }
+        for child in 0..self.packages.len() {
+            if self.packages[child].parent() == Some(package) && self.has_classes(child) {
+                let (child_top, child_bottom) = self.place_frame(child, layout);
+                top = top.min(child_top);
+                bottom = bottom.max(child_bottom);
+            }
+        }
+        layout
+            .corridors
+            .insert(Some(package), top - FRAME_PADDING / 2);
+        let (top, bottom) = (top - FRAME_PADDING - TAB_HEIGHT, bottom + FRAME_PADDING);
+        if let Some(frame) = layout.frames[package].as_mut() {
+            frame.1 = top;
+            frame.3 = bottom;
+        }
+        (top, bottom)
+    }
+
+    /// 各クラスの左上の座標 (1-indexed) とパッケージの枠、全体の大きさ
+    ///
+    /// 同じ階層のクラスは横に並べる。パッケージごとに縦の帯を割り当て、
+    /// パッケージのメンバーはその帯の中にまとめる。
+    pub(super) fn layout(&self, ranks: &[usize]) -> Layout {
+        let mut layout = Layout {
+            positions: vec![(0, 0); ranks.len()],
+            size: (0, 0),
+            frames: vec![None; self.packages.len()],
+            direct_bands: HashMap::new(),
+            corridors: HashMap::new(),
+        };
+        let mut xs = vec![0; ranks.len()];
+        self.place(None, 0, ranks, &mut xs, &mut layout);
+
+        // パッケージのタブと、最上位の通り道 (とその上のラベル) の分だけ全体を下げる
+        let depth = self.package_depth();
+        let headroom = self.corridor_headroom();
+        let top = if depth > 0 {
+            MARGIN * 2 + headroom + depth * (FRAME_PADDING + TAB_HEIGHT)
+        } else {
+            0
+        };
+        let rank_count = ranks.iter().copied().max().unwrap_or(0);
+        let mut heights = vec![0; rank_count];
+        // 型引数の箱が上にはみ出す分だけ、階層ごとに箱を下げる
+        let mut tops = vec![0; rank_count];
+        for (index, &rank) in ranks.iter().enumerate().skip(1) {
+            tops[rank - 1] = tops[rank - 1].max(self.template_overhang(index).1);
+        }
+        for (index, &rank) in ranks.iter().enumerate().skip(1) {
+            let height = self.footprint(index).1;
+            heights[rank - 1] = heights[rank - 1].max(tops[rank - 1] + height + self.rank_gap());
+        }
+        let mut ys = vec![top; rank_count + 1];
+        for rank in 0..rank_count {
+            ys[rank + 1] = ys[rank] + heights[rank];
+        }
+        for (index, &rank) in ranks.iter().enumerate().skip(1) {
+            layout.positions[index] = (xs[index], ys[rank - 1] + tops[rank - 1]);
+        }
+
+        let mut height = ys[rank_count];
+        layout.corridors.insert(None, MARGIN + headroom);
+        for package in 0..self.packages.len() {
+            if self.packages[package].parent().is_none() && self.has_classes(package) {
+                let (_, bottom) = self.place_frame(package, &mut layout);
+                height = height.max(bottom + NODE_GAP);
+            }
+        }
+        let width = match self.inner_width(None, ranks) {
+            0 => 0,
+            width => width + NODE_GAP,
+        };
+        layout.size = (width, height);
+        layout
+    }
+
+    /// 通り道を横に通る線の上に置くラベルの高さ
+    fn corridor_headroom(&self) -> usize {
+        if self.edges.iter().any(|edge| edge.label().is_some()) {
+            FONT_SIZE + PADDING
+        } else {
+            0
+        }
+    }
+
+    fn band_range(&self, layout: &Layout, container: Option<usize>, band: Band) -> (usize, usize) {
+        match band {
+            Band::Direct => layout.direct_bands[&container],
+            Band::Package(package) => {
+                let (left, _, right, _) = layout.frames[package].unwrap();
+                (left, right)
+            }
+        }
+    }
+
+    /// container の中で band の右 (right が false なら左) の隙間の中央の x
+    fn channel_x(
+        &self,
+        layout: &Layout,
+        container: Option<usize>,
+        band: Band,
+        right: bool,
+    ) -> usize {
+        let bands = self.bands(container);
+        let index = bands.iter().position(|&b| b == band).unwrap_or(0);
+        let (left, right_x) = self.band_range(layout, container, band);
+        let edge = if container.is_some() {
+            FRAME_PADDING
+        } else {
+            NODE_GAP
+        };
+        if right {
+            let space = if index + 1 == bands.len() {
+                edge
+            } else {
+                NODE_GAP
+            };
+            right_x + space / 2
+        } else {
+            let space = if index == 0 { edge } else { NODE_GAP };
+            left.saturating_sub(space / 2)
+        }
+    }
+
+    /// クラスの端 start から、lca の中の帯の脇の隙間に出るまでの点列。
+    /// 帯が枠の端になければ、脇の隙間を通ってパッケージの通り道に上がってから外に出る
+    fn exit_route(
+        &self,
+        layout: &Layout,
+        class: usize,
+        start: (usize, usize),
+        gap_y: usize,
+        lca: Option<usize>,
+        right: bool,
+    ) -> Vec<(usize, usize)> {
+        let mut points = vec![start, (start.0, gap_y)];
+        let mut container = self.package_of(class);
+        let mut band = Band::Direct;
+        let mut y = gap_y;
+        loop {
+            let x = self.channel_x(layout, container, band, right);
+            points.push((x, y));
+            let package = match container {
+                Some(package) if container != lca => package,
+                _ => break,
+            };
+            let bands = self.bands(container);
+            let outermost = if right { bands.last() } else { bands.first() };
+            if outermost != Some(&band) {
+                y = layout.corridors[&container];
+                points.push((x, y));
+            }
+            band = Band::Package(package);
+            container = self.packages[package].parent();
+        }
+        points
+    }
+
+    /// 線の点列。同じパッケージの中なら直線 (間の階層の箱を横切るなら箱の右を回る)、
+    /// 違うパッケージの間なら枠の外側の隙間を通る。
+    /// 自分自身への関係は右下の角を回る輪にする
+    pub(super) fn edge_route(&self, layout: &Layout, edge: &ClassEdge) -> Vec<(usize, usize)> {
+        let (from, to) = (edge.from(), edge.to());
+        if from == to {
+            let (x, y) = layout.positions[from];
+            let (right, bottom) = (x + self.node_width(from), y + self.node_height(from));
+            return vec![
+                (right - LOOP_SIZE, bottom),
+                (right - LOOP_SIZE, bottom + LOOP_SIZE),
+                (right + LOOP_SIZE, bottom + LOOP_SIZE),
+                (right + LOOP_SIZE, bottom - LOOP_SIZE),
+                (right, bottom - LOOP_SIZE),
+            ];
+        }
+        let (x1, y1, x2, y2) = self.edge_points(&layout.positions, edge);
+        if self.package_of(from) == self.package_of(to) {
+            let Some(x) = self.detour_x(layout, edge, (x1, y1), (x2, y2)) else {
+                return vec![(x1, y1), (x2, y2)];
+            };
+            // 同じ組の線が交わらないように、左の線ほど箱から離れたところを横に通す
+            let (index, count) = self.parallel_index(edge);
+            let gap = FRAME_PADDING / 2 + (count - 1 - index) * PADDING;
+            let (from_gap, to_gap) = if y1 < y2 {
+                (y1 + gap, y2 - gap)
+            } else {
+                (y1 - gap, y2 + gap)
+            };
+            let x = self.parallel_x(x, edge);
+            return vec![
+                (x1, y1),
+                (x1, from_gap),
+                (x, from_gap),
+                (x, to_gap),
+                (x2, to_gap),
+                (x2, y2),
+            ];
+        }
+        let from_path = self.package_path(from);
+        let to_path = self.package_path(to);
+        let common = from_path
+            .iter()
+            .zip(&to_path)
+            .take_while(|(a, b)| a == b)
+            .count();
+        let lca = common.checked_sub(1).map(|index| from_path[index]);
+        let branch = |path: &[usize]| path.get(common).map_or(Band::Direct, |&p| Band::Package(p));
+        let from_range = self.band_range(layout, lca, branch(&from_path));
+        let to_range = self.band_range(layout, lca, branch(&to_path));
+        let right = from_range.0 < to_range.0;
+
+        // 箱の外側へ少し出てから横に進む
+        let down = layout.positions[from].1 <= layout.positions[to].1;
+        let (from_gap, to_gap) = if down {
+            (y1 + FRAME_PADDING / 2, y2 - FRAME_PADDING / 2)
+        } else {
+            (y1 - FRAME_PADDING / 2, y2 + FRAME_PADDING / 2)
+        };
+        let mut points = self.exit_route(layout, from, (x1, y1), from_gap, lca, right);
+        let mut back = self.exit_route(layout, to, (x2, y2), to_gap, lca, !right);
+        let from_x = points.last().map_or(x1, |p| p.0);
+        let to_x = back.last().map_or(x2, |p| p.0);
+        if from_x != to_x {
+            // 隣り合っていない帯の間は、lca の中の通り道を通る
+            let y = layout.corridors[&lca];
+            points.push((from_x, y));
+            points.push((to_x, y));
+        }
+        back.reverse();
+        points.extend(back);
+        points.dedup();
+        // 同じ向きに続く点は省く
+        let mut route: Vec<(usize, usize)> = vec![];
+        for point in points {
+            match route[..] {
+                [.., a, b] if (a.0 == b.0 && b.0 == point.0) || (a.1 == b.1 && b.1 == point.1) => {
+                    *route.last_mut().unwrap() = point;
+                }
+                _ => route.push(point),
+            }
+        }
+        route
+    }
+
+    /// 直線が間の階層のクラスの箱を横切るなら、それらの階層の箱の右を回る縦の通り道の x
+    fn detour_x(
+        &self,
+        layout: &Layout,
+        edge: &ClassEdge,
+        (x1, y1): (usize, usize),
+        (x2, y2): (usize, usize),
+    ) -> Option<usize> {
+        let (top, bottom) = (y1.min(y2), y1.max(y2));
+        let between = (1..=self.nodes.len())
+            .filter(|&class| class != edge.from() && class != edge.to())
+            .filter(|&class| {
+                let y = layout.positions[class].1;
+                y < bottom && top < y + self.node_height(class)
+            })
+            .collect::<Vec<_>>();
+        // 直線が y を通るときの x
+        let at = |y: usize| {
+            let t = (y as f64 - y1 as f64) / (y2 as f64 - y1 as f64);
+            x1 as f64 + (x2 as f64 - x1 as f64) * t
+        };
+        let crosses = between.iter().any(|&class| {
+            let (x, y) = layout.positions[class];
+            let (a, b) = (
+                at(y.max(top)),
+                at((y + self.node_height(class)).min(bottom)),
+            );
+            a.min(b) < (x + self.node_width(class)) as f64 && a.max(b) > x as f64
+        });
+        if !crosses {
+            return None;
+        }
+        between
+            .iter()
+            .map(|&class| {
+                let has_loop = self
+                    .edges
+                    .iter()
+                    .any(|e| e.from() == class && e.to() == class);
+                layout.positions[class].0
+                    + self.footprint(class).0
+                    + if has_loop { LOOP_SIZE } else { 0 }
+            })
+            .max()
+            .map(|right| right + NODE_GAP / 2)
+    }
+
+    /// パッケージの枠。左上のタブに名前を書く
+    pub(super) fn make_frames(&self, layout: &Layout) -> Group {
+        let mut group = Group::new();
+        for (package, frame) in layout.frames.iter().enumerate() {
+            let Some((left, top, right, bottom)) = *frame else {
+                continue;
+            };
+            let tab = make_rect(self.tab_width(package), TAB_HEIGHT)
+                .set_theme(&self.theme)
+                .set("fill", "none")
+                .set("x", left)
+                .set("y", top);
+            let body = make_rect(right - left, bottom - top - TAB_HEIGHT)
+                .set_theme(&self.theme)
+                .set("fill", "none")
+                .set("x", left)
+                .set("y", top + TAB_HEIGHT);
+            let name = make_text(self.packages[package].name())
+                .set("x", left + PADDING)
+                .set("y", top + PADDING + FONT_SIZE)
+                .set("font-size", FONT_SIZE)
+                .set("fill", self.theme.color.text_primary);
+            group = group.add(tab).add(body).add(name);
+        }
+        group
+    }
+}
@@ -0,0 +1,240 @@
+use super::FONT_SIZE;
+use crate::helper::*;
+
+use svg::node::element::Group;
+
+/// メンバーの可視性
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Visibility {
+    /// `+`
+    Public,
+    /// `-`
+    Private,
+    /// `#`
+    Protected,
+    /// `~`
+    Package,
+}
+
+impl Visibility {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Visibility::Public => "+",
+            Visibility::Private => "-",
+            Visibility::Protected => "#",
+            Visibility::Package => "~",
+        }
+    }
+}
+
+impl From<bool> for Visibility {
+    /// true なら public、false なら private
+    fn from(public: bool) -> Self {
+        if public {
+            Visibility::Public
+        } else {
+            Visibility::Private
+        }
+    }
+}
+
+/// クラスの属性。`name: T = default` と表示する
+#[derive(PartialEq, Debug, Clone)]
+pub struct ClassElement {
+    visibility: Visibility,
+    name: String,
+    ty: Option<String>,
+    default: Option<String>,
+    is_static: bool,
+}
+
+/// メソッドの引数
+#[derive(PartialEq, Debug, Clone)]
+pub struct Param {
+    name: String,
+    ty: Option<String>,
+}
+
+/// クラスの操作。`name(arg: T): R` と表示する
+#[derive(PartialEq, Debug, Clone)]
+pub struct ClassMethod {
+    visibility: Visibility,
+    name: String,
+    params: Vec<Param>,
+    return_type: Option<String>,
+    is_static: bool,
+    is_abstract: bool,
+    /// false なら名前をそのまま表示する (タプルから作った場合)
+    parens: bool,
+}
+
+impl ClassElement {
+    pub fn new<T: Into<String>>(visibility: Visibility, name: T) -> Self {
+        ClassElement {
+            visibility,
+            name: name.into(),
+            ty: None,
+            default: None,
+            is_static: false,
+        }
+    }
+
+    pub fn with_type(mut self, ty: &str) -> Self {
+        self.ty = Some(ty.to_string());
+        self
+    }
+
+    pub fn with_default(mut self, default: &str) -> Self {
+        self.default = Some(default.to_string());
+        self
+    }
+
+    /// クラス属性 (下線付き)
+    pub fn with_static(mut self) -> Self {
+        self.is_static = true;
+        self
+    }
+
+    /// 可視性の記号を除いた表示用の文字列
+    pub fn label(&self) -> String {
+        let mut label = self.name.clone();
+        if let Some(ty) = &self.ty {
+            label += &format!(": {}", ty);
+        }
+        if let Some(default) = &self.default {
+            label += &format!(" = {}", default);
+        }
+        label
+    }
+
+    pub fn make_svg(&self) -> Group {
+        make_member(self.visibility, self.label(), self.is_static, false)
+    }
+}
+
+impl From<(bool, &str)> for ClassElement {
+    fn from((public, name): (bool, &str)) -> Self {
+        ClassElement::new(public.into(), name)
+    }
+}
+
+impl ClassMethod {
+    pub fn new<T: Into<String>>(visibility: Visibility, name: T) -> Self {
+        ClassMethod {
+            visibility,
+            name: name.into(),
+            params: vec![],
+            return_type: None,
+            is_static: false,
+            is_abstract: false,
+            parens: true,
+        }
+    }
+
+    /// 引数を追加する。型が空文字列なら名前だけ表示する
+    pub fn with_param(mut self, name: &str, ty: &str) -> Self {
+        self.parens = true;
+        self.params.push(Param {
+            name: name.to_string(),
+            ty: (!ty.is_empty()).then(|| ty.to_string()),
+        });
+        self
+    }
+
+    pub fn with_return(mut self, ty: &str) -> Self {
+        self.parens = true;
+        self.return_type = Some(ty.to_string());
+        self
+    }
+
+    /// クラス操作 (下線付き)
+    pub fn with_static(mut self) -> Self {
+        self.is_static = true;
+        self
+    }
+
+    /// 抽象操作 (斜体)
+    pub fn with_abstract(mut self) -> Self {
+        self.is_abstract = true;
+        self
+    }
+
+    /// 可視性の記号を除いた表示用の文字列
+    pub fn label(&self) -> String {
+        if !self.parens {
+            return self.name.clone();
+        }
+        let params = self
+            .params
+            .iter()
+            .map(|param| match &param.ty {
+                Some(ty) => format!("{}: {}", param.name, ty),
+                None => param.name.clone(),
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        match &self.return_type {
+            Some(ty) => format!("{}({}): {}", self.name, params, ty),
+            None => format!("{}({})", self.name, params),
+        }
+    }
+
+    pub fn make_svg(&self) -> Group {
+        make_member(
+            self.visibility,
+            self.label(),
+            self.is_static,
+            self.is_abstract,
+        )
+    }
+}
+
+impl From<(bool, &str)> for ClassMethod {
+    /// 名前だけの操作。以前の形式との互換のため、括弧は付けない
+    fn from((public, name): (bool, &str)) -> Self {
+        ClassMethod {
+            parens: false,
+            ..ClassMethod::new(public.into(), name)
+        }
+    }
+}
+
+/// 可視性の記号と本文を並べた 1 行
+fn make_member(visibility: Visibility, label: String, is_static: bool, is_abstract: bool) -> Group {
+    let mark = make_text(visibility.symbol())
+        .set("text-anchor", "middle")
+        .set("font-size", FONT_SIZE);
+    let mut text = make_text(label)
+        .set("x", FONT_SIZE)
+        .set("font-size", FONT_SIZE);
+    if is_static {
+        text = text.set("text-decoration", "underline");
+    }
+    if is_abstract {
+        text = text.set("font-style", "italic");
+    }
+    Group::new().add(mark).add(text)
+}
+
+#[test]
+fn member_labels() {
+    let count = ClassElement::new(Visibility::Private, "count")
+        .with_type("usize")
+        .with_default("0");
+    assert_eq!(count.label(), "count: usize = 0");
+    let area = ClassMethod::new(Visibility::Protected, "area")
+        .with_param("scale", "f64")
+        .with_param("unit", "")
+        .with_return("f64");
+    assert_eq!(area.label(), "area(scale: f64, unit): f64");
+    assert_eq!(
+        ClassMethod::new(Visibility::Package, "run").label(),
+        "run()"
+    );
+    assert_eq!(ClassMethod::from((true, "method1")).label(), "method1");
+    assert_eq!(
+        ClassElement::from((false, "x")),
+        ClassElement::new(Visibility::Private, "x")
+    );
+    assert_eq!(Visibility::Package.symbol(), "~");
+}
@@ -1,22 +1,54 @@
+mod edge;
+mod error;
+mod handle;
+mod kind;
+mod layout;
+mod member;
 mod node;
+mod package;
+
+pub use edge::{ClassEdge, Relation};
+pub use error::ClassError;
+pub use handle::{ClassId, ClassKey, PackageId};
+pub use kind::ClassKind;
+pub use member::{ClassElement, ClassMethod, Visibility};
 
 use crate::helper::*;
 use crate::theme::{Theme, ThemeName};
 use crate::MakeSvg;
+use layout::Layout;
 use node::*;
-use svg::node::element::{Group, Line};
+use package::Package;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use svg::node::element::path::Data;
+use svg::node::element::{Definitions, Group, Line, Path};
 use svg::Document;
 const FONT_SIZE: usize = 8;
 const PADDING: usize = 3;
 const MARGIN: usize = 5;
+/// クラスの箱の最小の幅
+const MIN_NODE_WIDTH: usize = 100;
+/// クラスの箱どうしの隙間
+const NODE_GAP: usize = 20;
+const NOTE_GAP: usize = 10;
+/// 同じクラスの組をつなぐ線どうしの間隔
+const EDGE_SPACING: usize = MARGIN * 2;
+/// メンバーの可視性の記号の x 座標
+const MEMBER_X: usize = 10;
 
 #[allow(dead_code)]
 pub struct ClassUML {
     name: String,
     nodes: Vec<ClassNode>,
-    edges: Vec<(usize, usize)>,
+    edges: Vec<ClassEdge>,
+    /// (クラスの番号 (1-indexed), 本文)
+    notes: Vec<(usize, String)>,
+    packages: Vec<Package>,
     theme: Theme,
-    bbox_cnt: (usize, usize),
+    metrics: TextMetrics,
+    /// 名前の左に種類を表す丸を付けるかどうか
+    badges: bool,
+    bbox_size: (usize, usize),
 }
 
 impl ClassUML {
@@ -25,49 +57,572 @@ impl ClassUML {
             name: name.into(),
             nodes: vec![],
             edges: vec![],
+            notes: vec![],
+            packages: vec![],
             theme: Theme::new(ThemeName::Default),
-            bbox_cnt: (0, 0),
+            metrics: TextMetrics::new(),
+            badges: false,
+            bbox_size: (0, 0),
+        }
+    }
+
+    /// 文字列の幅の測り方を設定する。フォントを設定すると SVG にも font-family が入る。
+    pub fn set_metrics(&mut self, metrics: TextMetrics) {
+        self.metrics = metrics;
+    }
+
+    /// クラスを追加し、関係やノートを付けるためのハンドルを返す
+    pub fn add_class(
+        &mut self,
+        name: &str,
+        elements: &[ClassElement],
+        methods: &[ClassMethod],
+    ) -> ClassId {
+        self.nodes
+            .push(ClassNode::new(name, elements.to_vec(), methods.to_vec()));
+        ClassId(self.nodes.len())
+    }
+
+    /// (public かどうか, 名前) だけでメンバーを指定してクラスを追加する
+    pub fn add_simple_class(
+        &mut self,
+        name: &str,
+        elements: &[(bool, &str)],
+        methods: &[(bool, &str)],
+    ) -> ClassId {
+        let elements = elements.iter().map(|&e| e.into()).collect::<Vec<_>>();
+        let methods = methods.iter().map(|&m| m.into()).collect::<Vec<_>>();
+        self.add_class(name, &elements, &methods)
+    }
+
+    /// 列挙型を追加する
+    pub fn add_enum(&mut self, name: &str, literals: &[&str]) -> ClassId {
+        let id = self.add_class(name, &[], &[]);
+        let node = &mut self.nodes[id.0 - 1];
+        node.set_kind(ClassKind::Enum);
+        node.add_literals(literals);
+        id
+    }
+
+    /// クラスの種類を変える
+    pub fn set_kind<'a, K: Into<ClassKey<'a>>>(
+        &mut self,
+        class: K,
+        kind: ClassKind,
+    ) -> Result<&Self, ClassError> {
+        let index = self.find_class(class)?;
+        self.nodes[index - 1].set_kind(kind);
+        Ok(self)
+    }
+
+    /// 種類とは別のステレオタイプを付ける
+    pub fn add_stereotype<'a, K: Into<ClassKey<'a>>>(
+        &mut self,
+        class: K,
+        stereotype: &str,
+    ) -> Result<&Self, ClassError> {
+        let index = self.find_class(class)?;
+        self.nodes[index - 1].add_stereotype(stereotype);
+        Ok(self)
+    }
+
+    /// `Repository<T: Entity>` のような型引数を追加する。境界が空文字列なら名前だけ表示する
+    pub fn add_type_param<'a, K: Into<ClassKey<'a>>>(
+        &mut self,
+        class: K,
+        name: &str,
+        bound: &str,
+    ) -> Result<&Self, ClassError> {
+        let index = self.find_class(class)?;
+        self.nodes[index - 1].add_type_param(name, bound);
+        Ok(self)
+    }
+
+    /// 最上位のパッケージを追加する
+    pub fn add_package<'a, K: Into<ClassKey<'a>> + Copy>(
+        &mut self,
+        name: &str,
+        members: &[K],
+    ) -> Result<PackageId, ClassError> {
+        self.push_package(name, None, members)
+    }
+
+    /// parent の中に入れ子のパッケージを追加する
+    pub fn add_subpackage<'a, K: Into<ClassKey<'a>> + Copy>(
+        &mut self,
+        parent: PackageId,
+        name: &str,
+        members: &[K],
+    ) -> Result<PackageId, ClassError> {
+        if parent.0 >= self.packages.len() {
+            return Err(ClassError::InvalidPackage(parent.0));
         }
+        self.push_package(name, Some(parent.0), members)
     }
 
-    pub fn add_class(&mut self, name: &str, elements: &[(bool, &str)], methods: &[(bool, &str)]) {
-        self.nodes.push(ClassNode::new(
-            name,
-            elements.iter().map(|&e| ClassElement::new(e)).collect(),
-            methods.iter().map(|&m| ClassMethod::new(m)).collect(),
-        ));
+    fn push_package<'a, K: Into<ClassKey<'a>> + Copy>(
+        &mut self,
+        name: &str,
+        parent: Option<usize>,
+        members: &[K],
+    ) -> Result<PackageId, ClassError> {
+        let members = members
+            .iter()
+            .map(|&member| self.find_class(member))
+            .collect::<Result<Vec<_>, _>>()?;
+        if let Some(&class) = members
+            .iter()
+            .find(|&&class| self.package_of(class).is_some())
+        {
+            let name = self.nodes[class - 1].name().to_string();
+            return Err(ClassError::AlreadyPackaged(name));
+        }
+        self.packages.push(Package::new(name, parent, members));
+        Ok(PackageId(self.packages.len() - 1))
     }
 
-    pub fn add_edges(&mut self, edges: &[(usize, usize)]) {
-        self.edges.extend_from_slice(edges);
+    /// 最上位のパッケージごとに 1 つの箱にまとめた図
+    ///
+    /// パッケージに入っていないクラスはそのまま残す。パッケージが関わる関係は向きごとに
+    /// 1 本の依存にまとめ、2 本以上をまとめたときは本数をラベルにする。
+    pub fn package_view(&self) -> ClassUML {
+        let mut view = ClassUML::new(self.name.clone());
+        view.metrics = self.metrics.clone();
+        view.badges = self.badges;
+        // 各クラスをまとめた先の番号 (1-indexed)
+        let mut targets = vec![0];
+        let mut packages = HashMap::new();
+        for class in 1..=self.nodes.len() {
+            let target = match self.package_path(class).first() {
+                Some(&package) => *packages.entry(package).or_insert_with(|| {
+                    let id = view.add_class(self.packages[package].name(), &[], &[]);
+                    view.nodes[id.0 - 1].package = true;
+                    id.0
+                }),
+                None => {
+                    view.nodes.push(self.nodes[class - 1].clone());
+                    view.nodes.len()
+                }
+            };
+            targets.push(target);
+        }
+        let mut dependencies = BTreeMap::new();
+        for edge in self.edges.iter() {
+            let (from, to) = (targets[edge.from()], targets[edge.to()]);
+            if from == to {
+                continue;
+            }
+            if self.package_of(edge.from()).is_none() && self.package_of(edge.to()).is_none() {
+                view.edges.push(edge.remap(from, to));
+            } else {
+                *dependencies.entry((from, to)).or_insert(0) += 1;
+            }
+        }
+        for ((from, to), count) in dependencies {
+            let mut edge = ClassEdge::new(from, to, Relation::Dependency);
+            if count > 1 {
+                edge.set_label(&count.to_string());
+            }
+            view.edges.push(edge);
+        }
+        view
     }
 
+    /// 名前の左に種類を表す丸で囲んだ文字を付ける
+    pub fn show_badges(&mut self, show: bool) {
+        self.badges = show;
+    }
+
+    /// ハンドル・名前・番号から 1-indexed の番号を求める
+    fn find_class<'a, K: Into<ClassKey<'a>>>(&self, class: K) -> Result<usize, ClassError> {
+        match class.into() {
+            ClassKey::Id(ClassId(index)) | ClassKey::Index(index) => {
+                if (1..=self.nodes.len()).contains(&index) {
+                    Ok(index)
+                } else {
+                    Err(ClassError::InvalidIndex(index))
+                }
+            }
+            ClassKey::Name(name) => self
+                .nodes
+                .iter()
+                .position(|node| node.name() == name)
+                .map(|index| index + 1)
+                .ok_or_else(|| ClassError::UnknownClass(name.to_string())),
+        }
+    }
+
+    /// 矢印のない関連を追加する
+    pub fn add_edges<'a, K: Into<ClassKey<'a>> + Copy>(
+        &mut self,
+        edges: &[(K, K)],
+    ) -> Result<&Self, ClassError> {
+        let plain = Relation::Association { navigable: false };
+        for &(from, to) in edges {
+            self.add_edge(from, to, plain)?;
+        }
+        Ok(self)
+    }
+
+    /// 種類付きの関係を追加する。汎化・実現は (子, 親)、コンポジション・集約は (全体, 部分) の順
+    pub fn add_relation<'a, K: Into<ClassKey<'a>>>(
+        &mut self,
+        from: K,
+        to: K,
+        kind: Relation,
+    ) -> Result<&Self, ClassError> {
+        self.add_edge(from, to, kind)?;
+        Ok(self)
+    }
+
+    pub fn add_relations<'a, K: Into<ClassKey<'a>> + Copy>(
+        &mut self,
+        relations: &[(K, K, Relation)],
+    ) -> Result<&Self, ClassError> {
+        for &(from, to, kind) in relations {
+            self.add_edge(from, to, kind)?;
+        }
+        Ok(self)
+    }
+
+    /// 関係を追加し、多重度・ロール名・ラベルを設定するために返す
+    pub fn add_edge<'a, K: Into<ClassKey<'a>>>(
+        &mut self,
+        from: K,
+        to: K,
+        kind: Relation,
+    ) -> Result<&mut ClassEdge, ClassError> {
+        let from = self.find_class(from)?;
+        let to = self.find_class(to)?;
+        self.edges.push(ClassEdge::new(from, to, kind));
+        Ok(self.edges.last_mut().unwrap())
+    }
+
+    /// クラスにノートを付ける
+    pub fn add_note<'a, K: Into<ClassKey<'a>>>(
+        &mut self,
+        class: K,
+        text: &str,
+    ) -> Result<&Self, ClassError> {
+        let class = self.find_class(class)?;
+        self.notes.push((class, text.to_string()));
+        Ok(self)
+    }
+
+    /// index 番目 (1-indexed) のクラスに付いたノートの (幅, 高さ) の一覧
+    fn note_sizes(&self, index: usize) -> Vec<(usize, usize)> {
+        self.notes
+            .iter()
+            .filter(|(class, _)| *class == index)
+            .map(|(_, text)| {
+                let lines = text.lines().count().max(1);
+                (
+                    self.metrics.lines_width(text, FONT_SIZE) + PADDING * 2 + NOTE_FOLD,
+                    lines * (FONT_SIZE + PADDING) + PADDING * 2,
+                )
+            })
+            .collect()
+    }
+
+    /// ノートと型引数の箱の右側のはみ出しを含めたクラスの (幅, 高さ)
+    fn footprint(&self, index: usize) -> (usize, usize) {
+        let node_width = self.node_width(index) + self.template_overhang(index).0;
+        let node_height = self.node_height(index);
+        let notes = self.note_sizes(index);
+        if notes.is_empty() {
+            return (node_width, node_height);
+        }
+        let width = notes.iter().map(|&(w, _)| w).max().unwrap_or(0);
+        let height = notes.iter().map(|&(_, h)| h + MARGIN).sum::<usize>();
+        (node_width + NOTE_GAP + width, node_height.max(height))
+    }
+
+    /// index 番目 (1-indexed) のクラスの箱の幅
+    fn node_width(&self, index: usize) -> usize {
+        self.nodes[index - 1].width(&self.metrics, self.badges)
+    }
+
+    /// index 番目 (1-indexed) のクラスの型引数の箱のはみ出し
+    fn template_overhang(&self, index: usize) -> (usize, usize) {
+        self.nodes[index - 1].template_overhang(&self.metrics)
+    }
+
+    /// index 番目 (1-indexed) のクラスの箱の高さ
+    fn node_height(&self, index: usize) -> usize {
+        self.nodes[index - 1].height()
+    }
+
+    /// 階層の間の隙間。線に文字が付くときは両端と中央の 3 行が収まるように広げる
+    fn rank_gap(&self) -> usize {
+        if self.edges.iter().any(|edge| edge.has_labels()) {
+            NODE_GAP.max((FONT_SIZE + PADDING) * 3 + MARGIN * 2)
+        } else {
+            NODE_GAP
+        }
+    }
+
+    /// クラスの右側にノートを並べ、点線でつなぐ
+    fn make_notes(&self, index: usize, (x, y): (usize, usize)) -> Group {
+        let mut group = Group::new();
+        let node_width = self.node_width(index);
+        let note_x = x + node_width + self.template_overhang(index).0 + NOTE_GAP;
+        let mut note_y = y;
+        let notes = self.notes.iter().filter(|(class, _)| *class == index);
+        for ((_, text), (width, height)) in notes.zip(self.note_sizes(index)) {
+            let connector = (
+                x + node_width,
+                y + self.node_height(index) / 2,
+                note_x,
+                note_y + height / 2,
+            )
+                .make_line()
+                .set("stroke", self.theme.color.line.primary)
+                .set("stroke-dasharray", "4");
+            let note = Group::new()
+                .add(make_note(width, height).set_theme(&self.theme))
+                .add(
+                    make_multiline_text(text, PADDING, FONT_SIZE + PADDING)
+                        .set("y", PADDING + FONT_SIZE)
+                        .set("fill", self.theme.color.text_primary)
+                        .set("font-size", FONT_SIZE),
+                )
+                .transform(note_x, note_y);
+            group = group.add(connector).add(note);
+            note_y += height + MARGIN;
+        }
+        group
+    }
+
+    /// 各クラスの階層 (1-indexed)
+    ///
+    /// 閉路があれば一部の辺を逆向きにしてから階層を決める。線は元の向きのまま引く
     pub fn get_ranks(&mut self) -> Vec<usize> {
-        let edges = topological_sort_edges(self.nodes.len(), &mut self.edges);
-        let rank = calc_rank(&self.nodes, &edges);
-        println!("rank: {:?}", &rank);
-        rank
-    }
-
-    pub fn make_edge(&self, ranks: &Vec<usize>, rranks: &Vec<usize>, from: usize, to: usize) -> Line {
-        let from_rank = ranks[from] - 1;
-        let to_rank = ranks[to] - 1;
-
-        let from_rrank = rranks[from];
-        let to_rrank = rranks[to];
-
-        let from_x = from_rrank * 120 + 50;
-        let from_y = from_rank * 120 + 100;
-        let to_x = to_rrank * 120 + 50;
-        let to_y = to_rank * 120;
-        Line::new()
-            .set("x1", from_x)
-            .set("y1", from_y)
-            .set("x2", to_x)
-            .set("y2", to_y)
+        let pairs = self
+            .edges
+            .iter()
+            .map(|edge| edge.rank_pair())
+            .filter(|(from, to)| from != to)
+            .collect::<Vec<_>>();
+        let reversed = feedback_arc_set(self.nodes.len(), &pairs);
+        let mut pairs = pairs
+            .iter()
+            .enumerate()
+            .map(|(index, &(from, to))| {
+                if reversed.contains(&index) {
+                    (to, from)
+                } else {
+                    (from, to)
+                }
+            })
+            .collect::<Vec<_>>();
+        let edges = topological_sort_edges(self.nodes.len(), &mut pairs);
+        calc_rank(&self.nodes, &edges)
+    }
+
+    /// 同じクラスの組をつなぐ線 (向きは問わない) の中での edge の (順番, 本数)
+    fn parallel_index(&self, edge: &ClassEdge) -> (usize, usize) {
+        let pair = |e: &ClassEdge| (e.from().min(e.to()), e.from().max(e.to()));
+        let parallel = self
+            .edges
+            .iter()
+            .filter(|e| e.from() != e.to() && pair(e) == pair(edge))
+            .collect::<Vec<_>>();
+        let index = parallel
+            .iter()
+            .position(|&e| std::ptr::eq(e, edge))
+            .unwrap_or(0);
+        (index, parallel.len().max(1))
+    }
+
+    /// 同じクラスの組をつなぐ線が重ならないように、順番に応じて x を左右へずらす
+    fn parallel_x(&self, x: usize, edge: &ClassEdge) -> usize {
+        let (index, count) = self.parallel_index(edge);
+        (x + index * EDGE_SPACING).saturating_sub((count - 1) * EDGE_SPACING / 2)
+    }
+
+    /// 線の (始点 x, 始点 y, 終点 x, 終点 y)。上にあるクラスの下辺から下にあるクラスの上辺へ引く
+    fn edge_points(
+        &self,
+        positions: &[(usize, usize)],
+        edge: &ClassEdge,
+    ) -> (usize, usize, usize, usize) {
+        let (from, to) = (edge.from(), edge.to());
+        let from_x = self.parallel_x(positions[from].0 + self.node_width(from) / 2, edge);
+        let to_x = self.parallel_x(positions[to].0 + self.node_width(to) / 2, edge);
+        let (from_y, to_y) = if positions[from].1 <= positions[to].1 {
+            (positions[from].1 + self.node_height(from), positions[to].1)
+        } else {
+            (positions[from].1, positions[to].1 + self.node_height(to))
+        };
+        (from_x, from_y, to_x, to_y)
+    }
+
+    /// 関係の種類に応じた端を付けた線
+    pub fn make_edge(&self, positions: &[(usize, usize)], edge: &ClassEdge) -> Line {
+        let kind = edge.kind();
+        let line = self
+            .edge_points(positions, edge)
+            .make_line()
             .set("stroke-width", 2)
             .set("stroke", "#000000")
+            .add_marker_start(&kind.marker_start())
+            .add_marker_end(&kind.marker_end());
+        if kind.is_dashed() {
+            line.set("stroke-dasharray", "4")
+        } else {
+            line
+        }
+    }
+
+    /// 折れ線の関係の線
+    fn make_route(&self, route: &[(usize, usize)], edge: &ClassEdge) -> Path {
+        let data = route[1..]
+            .iter()
+            .fold(Data::new().move_to(route[0]), |data, &point| {
+                data.line_to(point)
+            });
+        let kind = edge.kind();
+        let path = Path::new()
+            .set("d", data)
+            .set("fill", "none")
+            .set("stroke-width", 2)
+            .set("stroke", "#000000")
+            .add_marker_start(&kind.marker_start())
+            .add_marker_end(&kind.marker_end());
+        if kind.is_dashed() {
+            path.set("stroke-dasharray", "4")
+        } else {
+            path
+        }
+    }
+
+    /// 線に付ける文字の (x, y, text-anchor, 文字列) の一覧
+    ///
+    /// 多重度は線の右、ロール名は線の左に、クラスの箱の外側へ 1 行ずらして置く。
+    /// 中央のラベルは線の中点の右に置く。
+    /// 折れ線の場合は両端の線分と、真ん中の線分を使う。横向きの線分のラベルは上に置く。
+    fn edge_labels(&self, route: &[(usize, usize)], edge: &ClassEdge) -> Vec<EdgeLabel> {
+        let last = route.len() - 1;
+        let mut labels = vec![];
+        for (end, (x, y), (other_x, other_y)) in [
+            (edge.from_end(), route[0], route[1]),
+            (edge.to_end(), route[last], route[last - 1]),
+        ] {
+            let top = if other_y > y {
+                y + PADDING
+            } else {
+                y - PADDING - FONT_SIZE
+            };
+            let (left, right) = line_span((x, y), (other_x, other_y), top);
+            if let Some(multiplicity) = &end.multiplicity {
+                labels.push(EdgeLabel::new(
+                    right + MARGIN * 2,
+                    top,
+                    "start",
+                    multiplicity,
+                ));
+            }
+            if let Some(role) = &end.role {
+                labels.push(EdgeLabel::new(
+                    left.saturating_sub(MARGIN * 2),
+                    top,
+                    "end",
+                    role,
+                ));
+            }
+        }
+        if let Some(label) = edge.label() {
+            let middle = last / 2;
+            let ((x1, y1), (x2, y2)) = (route[middle], route[middle + 1]);
+            if y1 == y2 && route.len() > 2 {
+                let top = y1 - PADDING - FONT_SIZE;
+                labels.push(EdgeLabel::new((x1 + x2) / 2, top, "middle", label));
+            } else {
+                let top = (y1 + y2) / 2 - FONT_SIZE / 2;
+                let (_, right) = line_span((x1, y1), (x2, y2), top);
+                labels.push(EdgeLabel::new(right + MARGIN, top, "start", label));
+            }
+        }
+        labels
+    }
+
+    /// 線に付ける文字が x = 0 より左にはみ出す幅と、文字の右端
+    fn edge_labels_extent(&self, layout: &Layout) -> (usize, usize) {
+        self.edges
+            .iter()
+            .flat_map(|edge| self.edge_labels(&self.edge_route(layout, edge), edge))
+            .map(|label| {
+                let width = self.metrics.width(&label.text, FONT_SIZE);
+                match label.anchor {
+                    "start" => (0, label.x + width),
+                    "middle" => ((width / 2).saturating_sub(label.x), label.x + width / 2),
+                    _ => (width.saturating_sub(label.x), label.x),
+                }
+            })
+            .fold((0, 0), |(left, right), (label_left, label_right)| {
+                (left.max(label_left), right.max(label_right))
+            })
+    }
+
+    fn make_edge_labels(&self, route: &[(usize, usize)], edge: &ClassEdge) -> Group {
+        self.edge_labels(route, edge)
+            .into_iter()
+            .fold(Group::new(), |group, label| {
+                group.add(
+                    make_text(label.text)
+                        .set("x", label.x)
+                        .set("y", label.y + FONT_SIZE)
+                        .set("text-anchor", label.anchor)
+                        .set("font-size", FONT_SIZE)
+                        .set("fill", self.theme.color.text_primary),
+                )
+            })
     }
+
+    /// 使われている端の marker の定義
+    fn make_markers(&self) -> Definitions {
+        let markers = self
+            .edges
+            .iter()
+            .flat_map(|edge| [edge.kind().marker_start(), edge.kind().marker_end()])
+            .filter(|marker| *marker != Markers::None)
+            .collect::<BTreeSet<_>>();
+        markers.iter().fold(Definitions::new(), |defs, marker| {
+            defs.add(marker.make_svg())
+        })
+    }
+}
+
+/// 線に付ける文字。y は文字の上端
+struct EdgeLabel {
+    x: usize,
+    y: usize,
+    anchor: &'static str,
+    text: String,
+}
+
+impl EdgeLabel {
+    fn new(x: usize, y: usize, anchor: &'static str, text: &str) -> Self {
+        EdgeLabel {
+            x,
+            y,
+            anchor,
+            text: text.to_string(),
+        }
+    }
+}
+
+/// (x, y) から other へ向かう線が、top から 1 行分の高さで通る x の範囲
+fn line_span((x, y): (usize, usize), other: (usize, usize), top: usize) -> (usize, usize) {
+    let at = |row_y: usize| {
+        if other.1 == y {
+            return x as f64;
+        }
+        let t = (row_y as f64 - y as f64) / (other.1 as f64 - y as f64);
+        x as f64 + (other.0 as f64 - x as f64) * t.clamp(0.0, 1.0)
+    };
+    let (a, b) = (at(top), at(top + FONT_SIZE));
+    (a.min(b).floor() as usize, a.max(b).ceil() as usize)
 }
 
 // struct Line
@@ -76,47 +631,60 @@ impl MakeSvg for ClassUML {
     fn make_svg(&mut self) -> Document {
         let mut group = Group::new();
         // 0-n
-        let mut ranks = self.get_ranks();
-        
-        let mut cnt = vec![0; ranks.len()];
-        let mut row_ranks = vec![0usize; ranks.len()];
+        let ranks = self.get_ranks();
+        let layout = self.layout(&ranks);
+        let positions = &layout.positions;
+        if !self.packages.is_empty() {
+            group = group.add(self.make_frames(&layout));
+        }
+
         let mut node_vec = vec![];
         for (index, node) in self.nodes.iter().enumerate() {
-            let rank = ranks[index + 1] - 1;
-            let node_svg = node
-                .make_svg(&self.theme)
-                .transform(120 * cnt[rank], 120 * rank);
-            node_vec.push(node_svg);
-            row_ranks[index + 1] = cnt[rank];
-            cnt[rank] += 1;
-        }
-
-        for (index, _) in self.nodes.iter().enumerate() {
-            for edge in self.edges.iter() {
-                if edge.0 == index + 1 {
-                    println!("{:?} {}", edge, index);
-                    let edge_svg = self.make_edge(&ranks, &row_ranks, edge.0, edge.1);
-                    group = group.add(edge_svg)
-                }
+            let (x, y) = positions[index + 1];
+            node_vec.push(
+                node.make_svg(&self.theme, &self.metrics, self.badges)
+                    .transform(x, y),
+            );
+            if self.notes.iter().any(|(class, _)| *class == index + 1) {
+                node_vec.push(self.make_notes(index + 1, (x, y)));
             }
         }
+
+        let mut route_right = 0;
+        for edge in self.edges.iter() {
+            let route = self.edge_route(&layout, edge);
+            route_right = route
+                .iter()
+                .fold(route_right, |right, &(x, _)| right.max(x));
+            group = if route.len() == 2 {
+                group.add(self.make_edge(positions, edge))
+            } else {
+                group.add(self.make_route(&route, edge))
+            };
+            group = group.add(self.make_edge_labels(&route, edge));
+        }
         for node_svg in node_vec {
             group = group.add(node_svg);
         }
-        group = group.transform(10, 10);
-        ranks.reverse();
-        let w = cnt.iter().fold(0, |acc, &c| if c > 0 { c } else { acc });
-        let h = *ranks.iter().max().unwrap();
-        self.bbox_cnt = (w, h);
+        // 左にはみ出す文字が余白に収まらなければ、その分だけ図全体を右へずらす
+        let (overhang, right) = self.edge_labels_extent(&layout);
+        let shift = overhang.saturating_sub(10);
+        group = group.transform(10 + shift, 10);
+        if let Some(family) = self.metrics.font_family() {
+            group = group.set("font-family", family);
+        }
+        let (width, height) = layout.size;
+        // 線の右端は、図全体をずらす分と余白を足して収める
+        let width = width.max(right).max(route_right + NODE_GAP);
+        self.bbox_size = (width + shift, height);
         Document::new()
+            .add(self.make_markers())
             .add(group)
             .set("viewBox", self.bounding_box())
     }
 
     fn bounding_box(&self) -> (usize, usize, usize, usize) {
-        let x = self.bbox_cnt.0 * 120;
-        let y = self.bbox_cnt.1 * 120;
-        (0, 0, x, y)
+        (0, 0, self.bbox_size.0, self.bbox_size.1)
     }
 }
 
@@ -2,29 +2,32 @@ use super::*;
 use crate::helper::*;
 use crate::theme::Theme;
 
-use svg::node::element::{Group, Line, Text};
-use svg::node::Text as TextNode;
+use super::member::*;
+use svg::node::element::{Circle, Group};
 
-#[allow(dead_code)]
-#[derive(PartialEq)]
+/// 種類を表す丸の半径
+const BADGE_RADIUS: usize = 6;
+/// まとめたパッケージの箱のタブの高さ
+const PACKAGE_TAB: usize = FONT_SIZE;
+/// 型引数の箱がクラスの右辺からはみ出す幅
+pub const TEMPLATE_OVERHANG: usize = MARGIN * 2;
+
+#[derive(PartialEq, Clone)]
 pub struct ClassNode {
     name: String,
+    kind: ClassKind,
+    /// まとめて 1 つの箱にしたパッケージ。メンバーの区画を持たない
+    pub(super) package: bool,
+    /// 種類以外に付けるステレオタイプ
+    stereotypes: Vec<String>,
+    /// 列挙型のリテラル
+    literals: Vec<String>,
+    /// 型引数の (名前, 境界)
+    type_params: Vec<(String, Option<String>)>,
     elements: Vec<ClassElement>,
     methods: Vec<ClassMethod>,
 }
 
-#[derive(PartialEq)]
-pub struct ClassElement {
-    public: bool,
-    name: String,
-}
-
-#[derive(PartialEq)]
-pub struct ClassMethod {
-    public: bool,
-    name: String,
-}
-
 impl ClassNode {
     pub fn new<T: Into<String>>(
         name: T,
@@ -33,53 +36,305 @@ impl ClassNode {
     ) -> Self {
         ClassNode {
             name: name.into(),
+            kind: ClassKind::Class,
+            package: false,
+            stereotypes: vec![],
+            literals: vec![],
+            type_params: vec![],
             elements,
             methods,
         }
     }
-    pub fn make_svg(&self, theme: &Theme) -> Group {
-        let node_rect = make_rect(100, 100).set_theme(theme);
-        let node_text = self.make_text();
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn set_kind(&mut self, kind: ClassKind) {
+        self.kind = kind;
+    }
+
+    pub fn add_stereotype(&mut self, stereotype: &str) {
+        self.stereotypes.push(stereotype.to_string());
+    }
+
+    pub fn add_literals(&mut self, literals: &[&str]) {
+        self.literals
+            .extend(literals.iter().map(|literal| literal.to_string()));
+    }
+
+    /// 型引数を追加する。境界が空文字列なら名前だけ表示する
+    pub fn add_type_param(&mut self, name: &str, bound: &str) {
+        self.type_params.push((
+            name.to_string(),
+            (!bound.is_empty()).then(|| bound.to_string()),
+        ));
+    }
+
+    /// `T: Entity, U` のような型引数の箱の中身
+    fn type_params(&self) -> Option<String> {
+        let params = self
+            .type_params
+            .iter()
+            .map(|(name, bound)| match bound {
+                Some(bound) => format!("{}: {}", name, bound),
+                None => name.clone(),
+            })
+            .collect::<Vec<_>>();
+        (!params.is_empty()).then(|| params.join(", "))
+    }
+
+    /// 型引数の箱の (幅, 高さ)
+    fn template_size(&self, metrics: &TextMetrics) -> Option<(usize, usize)> {
+        self.type_params().map(|params| {
+            (
+                metrics.width(&params, FONT_SIZE) + PADDING * 2,
+                FONT_SIZE + PADDING * 2,
+            )
+        })
+    }
+
+    /// 型引数の箱がクラスの箱からはみ出す (右, 上) の大きさ
+    pub fn template_overhang(&self, metrics: &TextMetrics) -> (usize, usize) {
+        match self.template_size(metrics) {
+            Some((_, height)) => (TEMPLATE_OVERHANG, height / 2),
+            None => (0, 0),
+        }
+    }
+
+    /// `«interface, entity»` のような名前の上の行
+    fn stereotype(&self) -> Option<String> {
+        let stereotypes = self
+            .kind
+            .stereotype()
+            .into_iter()
+            .chain(self.stereotypes.iter().map(|s| s.as_str()))
+            .collect::<Vec<_>>();
+        (!stereotypes.is_empty()).then(|| format!("«{}»", stereotypes.join(", ")))
+    }
+
+    /// 名前・ステレオタイプ・メンバーが収まる幅。MIN_NODE_WIDTH より狭くはならない。
+    /// badge が true なら名前の左右に丸の分の余白をとる。
+    pub fn width(&self, metrics: &TextMetrics, badge: bool) -> usize {
+        let badge_width = if badge {
+            (BADGE_RADIUS * 2 + PADDING) * 2
+        } else {
+            0
+        };
+        let title = metrics.width(&self.name, FONT_SIZE * 3 / 2) + PADDING * 2 + badge_width;
+        let stereotype = self
+            .stereotype()
+            .map(|s| metrics.width(&s, FONT_SIZE) + PADDING * 2)
+            .unwrap_or(0);
+        let literals = self
+            .literals
+            .iter()
+            .map(|literal| MEMBER_X + metrics.width(literal, FONT_SIZE) + PADDING)
+            .max()
+            .unwrap_or(0);
+        let members = self
+            .elements
+            .iter()
+            .map(|e| e.label())
+            .chain(self.methods.iter().map(|m| m.label()))
+            .map(|label| MEMBER_X + FONT_SIZE + metrics.width(&label, FONT_SIZE) + PADDING)
+            .max()
+            .unwrap_or(0);
+        let template = self
+            .template_size(metrics)
+            .map(|(width, _)| width)
+            .unwrap_or(0);
+        MIN_NODE_WIDTH
+            .max(title)
+            .max(template)
+            .max(stereotype)
+            .max(literals)
+            .max(members)
+    }
+
+    /// 名前の区画の高さ。ステレオタイプがあれば 1 行増える
+    fn title_height(&self) -> usize {
+        match self.stereotype() {
+            Some(_) => FONT_SIZE * 2 + FONT_SIZE + PADDING,
+            None => FONT_SIZE * 2,
+        }
+    }
+
+    /// 列挙型のリテラルの区画の高さ。列挙型でなければ 0
+    fn literals_height(&self) -> usize {
+        if self.kind == ClassKind::Enum {
+            (FONT_SIZE + PADDING) * self.literals.len() + MARGIN * 2
+        } else {
+            0
+        }
+    }
+
+    /// 属性の区画の高さ
+    fn elements_height(&self) -> usize {
+        (FONT_SIZE + PADDING) * self.elements.len() + MARGIN * 2
+    }
+
+    /// 操作の区画の高さ
+    fn methods_height(&self) -> usize {
+        (FONT_SIZE + PADDING) * self.methods.len() + MARGIN * 2
+    }
+
+    /// 名前・属性・操作の区画の高さの合計。パッケージはタブと名前だけ
+    pub fn height(&self) -> usize {
+        if self.package {
+            return PACKAGE_TAB + self.title_height() + MARGIN;
+        }
+        self.title_height()
+            + self.literals_height()
+            + self.elements_height()
+            + self.methods_height()
+    }
+
+    pub fn make_svg(&self, theme: &Theme, metrics: &TextMetrics, badge: bool) -> Group {
+        let width = self.width(metrics, badge);
+        if self.package {
+            return self.make_package_svg(theme, width, badge);
+        }
+        let node_rect = make_rect(width, self.height()).set_theme(theme);
+        let node_text = self.make_text(width, badge);
+        let literals_svg = self.make_literals_svg();
         let elements_svg = self.make_elements_svg();
         let methods_svg = self.make_methods_svg();
 
-        let height_title = FONT_SIZE * 2;
-        let line1 = Line::new()
-            .set("x1", 0)
-            .set("y1", height_title)
-            .set("x2", 100)
-            .set("y2", height_title)
-            .set("stroke", "#000");
-        let elements_height = (FONT_SIZE + PADDING) * self.elements.len();
-
-        let line2 = Line::new()
-            .set("x1", 0)
-            .set("y1", height_title + elements_height + MARGIN * 2)
-            .set("x2", 100)
-            .set("y2", height_title + elements_height + MARGIN * 2)
-            .set("stroke", "#000");
-
-        Group::new()
-            .add(node_rect)
-            .add(line1)
-            .add(line2)
+        // 区画の境界線
+        let mut group = Group::new().add(node_rect);
+        let mut line_y = self.title_height();
+        for height in [self.literals_height(), self.elements_height()] {
+            if height > 0 {
+                group = group.add(make_line(0, line_y, width, line_y).set("stroke", "#000"));
+                line_y += height;
+            }
+        }
+        group = group.add(make_line(0, line_y, width, line_y).set("stroke", "#000"));
+
+        group = group
             .add(node_text)
+            .add(literals_svg)
             .add(elements_svg)
-            .add(methods_svg)
+            .add(methods_svg);
+        match self.make_template(width, metrics, theme) {
+            Some(template) => group.add(template),
+            None => group,
+        }
+    }
+
+    /// 左上にタブの付いたフォルダの形の箱
+    fn make_package_svg(&self, theme: &Theme, width: usize, badge: bool) -> Group {
+        let tab = make_rect(width / 3, PACKAGE_TAB).set_theme(theme);
+        let body = make_rect(width, self.height() - PACKAGE_TAB)
+            .set_theme(theme)
+            .set("y", PACKAGE_TAB);
+        Group::new().add(tab).add(body).add(
+            self.make_text(width, badge)
+                .set("transform", format!("translate(0, {})", PACKAGE_TAB)),
+        )
+    }
+
+    /// 右上の角に重ねる、型引数を書いた破線の箱
+    fn make_template(&self, width: usize, metrics: &TextMetrics, theme: &Theme) -> Option<Group> {
+        let params = self.type_params()?;
+        let (box_width, box_height) = self.template_size(metrics)?;
+        let x = width + TEMPLATE_OVERHANG - box_width;
+        let y = -((box_height / 2) as i32);
+        Some(
+            Group::new()
+                .add(
+                    make_rect(box_width, box_height)
+                        .set_theme(theme)
+                        .set("stroke-dasharray", "3"),
+                )
+                .add(
+                    make_text(params)
+                        .set("x", PADDING)
+                        .set("y", PADDING + FONT_SIZE)
+                        .set("font-size", FONT_SIZE),
+                )
+                .set("transform", format!("translate({}, {})", x, y)),
+        )
     }
 
-    pub fn make_text(&self) -> Text {
-        let text_node = TextNode::new(self.name.clone());
-        Text::new()
-            .add(text_node)
-            .set("x", 50) // width / 2
-            .set("y", 3 * FONT_SIZE / 2)
+    /// ステレオタイプ・名前と、badge が true なら種類を表す丸
+    pub fn make_text(&self, width: usize, badge: bool) -> Group {
+        let title_y = self.title_height() - FONT_SIZE / 2;
+        let mut title = make_text(self.name.clone())
+            .set("x", width / 2)
+            .set("y", title_y)
             .set("font-size", FONT_SIZE * 3 / 2)
-            .set("text-anchor", "middle")
+            .set("text-anchor", "middle");
+        if self.kind.is_italic() {
+            title = title.set("font-style", "italic");
+        }
+        let mut group = Group::new();
+        if let Some(stereotype) = self.stereotype() {
+            group = group.add(
+                make_text(stereotype)
+                    .set("x", width / 2)
+                    .set("y", PADDING + FONT_SIZE)
+                    .set("font-size", FONT_SIZE)
+                    .set("text-anchor", "middle"),
+            );
+        }
+        if badge {
+            let (cx, cy) = (PADDING + BADGE_RADIUS, title_y - FONT_SIZE / 2);
+            let (letter, color) = if self.package {
+                ("P", "#d9d9d9")
+            } else {
+                (self.kind.letter(), self.kind.badge_color())
+            };
+            group = group
+                .add(
+                    Circle::new()
+                        .set("cx", cx)
+                        .set("cy", cy)
+                        .set("r", BADGE_RADIUS)
+                        .set("fill", color)
+                        .set("stroke", "#000"),
+                )
+                .add(
+                    make_text(letter)
+                        .set("x", cx)
+                        .set("y", cy + FONT_SIZE * 3 / 8)
+                        .set("font-size", FONT_SIZE)
+                        .set("font-weight", "bold")
+                        .set("text-anchor", "middle"),
+                );
+        }
+        group.add(title)
+    }
+
+    /// 列挙型のリテラルの一覧
+    fn make_literals_svg(&self) -> Group {
+        let mut group = Group::new().set(
+            "transform",
+            format!("translate(0, {})", self.title_height()),
+        );
+        if self.kind != ClassKind::Enum {
+            return group;
+        }
+        for (index, literal) in self.literals.iter().enumerate() {
+            group = group.add(
+                make_text(literal.clone())
+                    .set("x", MEMBER_X)
+                    .set("y", MARGIN + FONT_SIZE + index * (FONT_SIZE + PADDING))
+                    .set("font-size", FONT_SIZE),
+            );
+        }
+        group
     }
 
     pub fn make_elements_svg(&self) -> Group {
-        let mut group = Group::new().set("transform", format!("translate(0, {})", 2 * FONT_SIZE));
+        let mut group = Group::new().set(
+            "transform",
+            format!(
+                "translate(0, {})",
+                self.title_height() + self.literals_height()
+            ),
+        );
         let elements_svg: Vec<Group> = self
             .elements
             .iter()
@@ -88,7 +343,8 @@ impl ClassNode {
                 el.make_svg().set(
                     "transform",
                     format!(
-                        "translate(10, {})",
+                        "translate({}, {})",
+                        MEMBER_X,
                         MARGIN + FONT_SIZE + index * (FONT_SIZE + PADDING)
                     ),
                 )
@@ -106,14 +362,15 @@ impl ClassNode {
             "transform",
             format!(
                 "translate(0,{})",
-                2 * FONT_SIZE + (FONT_SIZE + PADDING) * self.elements.len() + MARGIN * 2
+                self.title_height() + self.literals_height() + self.elements_height()
             ),
         );
         let methods_svg = self.methods.iter().enumerate().map(|(index, method)| {
             method.make_svg().set(
                 "transform",
                 format!(
-                    "translate(10, {})",
+                    "translate({}, {})",
+                    MEMBER_X,
                     MARGIN + FONT_SIZE + index * (FONT_SIZE + PADDING)
                 ),
             )
@@ -124,61 +381,3 @@ impl ClassNode {
         group
     }
 }
-
-impl ClassElement {
-    pub fn new<T: Into<String>>(e: (bool, T)) -> Self {
-        Self {
-            public: e.0,
-            name: e.1.into(),
-        }
-    }
-
-    pub fn make_svg(&self) -> Group {
-        Group::new().add(self.make_mark()).add(self.make_text())
-    }
-
-    pub fn make_text(&self) -> Text {
-        let text_node = TextNode::new(self.name.clone());
-        Text::new()
-            .add(text_node)
-            .set("x", FONT_SIZE)
-            .set("font-size", FONT_SIZE)
-    }
-    pub fn make_mark(&self) -> Text {
-        let mark = if self.public { "+" } else { "-" };
-        let mark_node = TextNode::new(mark);
-        Text::new()
-            .add(mark_node)
-            .set("text-anchor", "middle")
-            .set("font-size", FONT_SIZE)
-    }
-}
-
-impl ClassMethod {
-    pub fn new<T: Into<String>>(m: (bool, T)) -> Self {
-        Self {
-            public: m.0,
-            name: m.1.into(),
-        }
-    }
-
-    fn make_svg(&self) -> Group {
-        Group::new().add(self.make_mark()).add(self.make_text())
-    }
-
-    fn make_text(&self) -> Text {
-        let text_node = TextNode::new(self.name.clone());
-        Text::new()
-            .add(text_node)
-            .set("x", FONT_SIZE)
-            .set("font-size", FONT_SIZE)
-    }
-    fn make_mark(&self) -> Text {
-        let mark = if self.public { "+" } else { "-" };
-        let mark_node = TextNode::new(mark);
-        Text::new()
-            .add(mark_node)
-            .set("text-anchor", "middle")
-            .set("font-size", FONT_SIZE)
-    }
-}
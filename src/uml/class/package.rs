@@ -0,0 +1,33 @@
+/// クラスをまとめるパッケージ
+///
+/// `members` は直下のクラスの番号 (1-indexed)。入れ子のパッケージは `parent` で親を指す。
+pub struct Package {
+    name: String,
+    parent: Option<usize>,
+    members: Vec<usize>,
+}
+
+impl Package {
+    pub fn new(name: &str, parent: Option<usize>, members: Vec<usize>) -> Self {
+        Package {
+            name: name.to_string(),
+            parent,
+            members,
+        }
+    }
+
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[inline]
+    pub fn parent(&self) -> Option<usize> {
+        self.parent
+    }
+
+    #[inline]
+    pub fn members(&self) -> &[usize] {
+        &self.members
+    }
+}
@@ -1,4 +1,9 @@
 mod class;
 mod sequence;
-pub use class::ClassUML;
-pub use sequence::Sequence;
+pub use class::{
+    ClassEdge, ClassElement, ClassError, ClassId, ClassKey, ClassKind, ClassMethod, ClassUML,
+    PackageId, Relation, Visibility,
+};
+pub use sequence::{
+    FragmentKind, Gate, MessageKind, NotePosition, ParticipantKind, Sequence, SequenceError,
+};
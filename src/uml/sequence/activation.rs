@@ -0,0 +1,65 @@
+/// ライフライン上の実行仕様 (activation bar)
+///
+/// `start` / `end` はメッセージの行番号。`end` が `None` の場合はライフラインの下端まで伸びる。
+pub struct Activation {
+    node: usize,
+    level: usize,
+    start: usize,
+    end: Option<usize>,
+}
+
+impl Activation {
+    pub fn new(node: usize, level: usize, start: usize) -> Self {
+        Activation {
+            node,
+            level,
+            start,
+            end: None,
+        }
+    }
+
+    #[inline]
+    pub fn node(&self) -> usize {
+        self.node
+    }
+
+    #[inline]
+    pub fn level(&self) -> usize {
+        self.level
+    }
+
+    #[inline]
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    #[inline]
+    pub fn end(&self) -> Option<usize> {
+        self.end
+    }
+
+    #[inline]
+    pub fn is_open(&self) -> bool {
+        self.end.is_none()
+    }
+
+    pub fn close(&mut self, end: usize) {
+        self.end = Some(end.max(self.start));
+    }
+
+    /// 指定した行でこの activation が有効かどうか
+    pub fn contains(&self, row: usize) -> bool {
+        self.start <= row && self.end.is_none_or(|end| row <= end)
+    }
+}
+
+#[test]
+fn activation_contains() {
+    let mut activation = Activation::new(0, 0, 1);
+    assert!(!activation.contains(0));
+    assert!(activation.contains(5));
+    activation.close(3);
+    assert!(activation.contains(1));
+    assert!(activation.contains(3));
+    assert!(!activation.contains(4));
+}
@@ -0,0 +1,111 @@
+/// メッセージの自動採番
+///
+/// `counters` は階層ごとの次の番号。階層番号が有効な場合はフラグメントに入るたびに 1 段深くなる。
+pub struct Autonumber {
+    enabled: bool,
+    hierarchical: bool,
+    step: usize,
+    format: String,
+    counters: Vec<usize>,
+    /// 開いているフラグメントごとに階層を追加したかどうか
+    nested: Vec<bool>,
+}
+
+impl Autonumber {
+    pub fn new() -> Self {
+        Autonumber {
+            enabled: false,
+            hierarchical: false,
+            step: 1,
+            format: "{}".to_string(),
+            counters: vec![1],
+            nested: Vec::new(),
+        }
+    }
+
+    /// start から step ずつ採番を始める。開いているフラグメントの階層はそのまま残す。
+    pub fn start(&mut self, start: usize, step: usize) {
+        self.enabled = true;
+        self.step = step;
+        self.counters.truncate(1);
+        self.counters[0] = start;
+        self.nested.iter_mut().for_each(|nested| *nested = false);
+    }
+
+    /// 採番を止める。番号は resume で続きから再開する。
+    #[inline]
+    pub fn stop(&mut self) {
+        self.enabled = false;
+    }
+
+    #[inline]
+    pub fn resume(&mut self) {
+        self.enabled = true;
+    }
+
+    /// `{}` を番号に置き換える書式
+    pub fn set_format(&mut self, format: &str) {
+        self.format = format.to_string();
+    }
+
+    /// フラグメントの中を 1.1, 1.2 のように採番するかどうか
+    pub fn set_hierarchical(&mut self, hierarchical: bool) {
+        self.hierarchical = hierarchical;
+    }
+
+    /// 次のメッセージの番号。無効なら None
+    pub fn next(&mut self) -> Option<String> {
+        if !self.enabled {
+            return None;
+        }
+        let number = self
+            .counters
+            .iter()
+            .map(|n| n.to_string())
+            .collect::<Vec<_>>()
+            .join(".");
+        self.advance();
+        Some(self.format.replace("{}", &number))
+    }
+
+    // 最も内側の番号を進める。一番外側だけ step を使う。
+    fn advance(&mut self) {
+        let last = self.counters.len() - 1;
+        self.counters[last] += if last == 0 { self.step } else { 1 };
+    }
+
+    /// フラグメントに入る。内側の番号は 1 から始まる。
+    pub fn begin_fragment(&mut self) {
+        let nested = self.enabled && self.hierarchical;
+        if nested {
+            self.counters.push(1);
+        }
+        self.nested.push(nested);
+    }
+
+    /// フラグメントを出る。内側で採番していれば外側の番号を進める。
+    pub fn end_fragment(&mut self) {
+        if self.nested.pop() == Some(true) && self.counters.pop() != Some(1) {
+            self.advance();
+        }
+    }
+}
+
+#[test]
+fn autonumber_hierarchical() {
+    let mut numbers = Autonumber::new();
+    assert_eq!(numbers.next(), None);
+    numbers.start(10, 5);
+    numbers.set_hierarchical(true);
+    assert_eq!(numbers.next().as_deref(), Some("10"));
+    numbers.begin_fragment();
+    assert_eq!(numbers.next().as_deref(), Some("15.1"));
+    assert_eq!(numbers.next().as_deref(), Some("15.2"));
+    numbers.end_fragment();
+    assert_eq!(numbers.next().as_deref(), Some("20"));
+    numbers.stop();
+    assert_eq!(numbers.next(), None);
+    numbers.resume();
+    numbers.set_format("[{}]");
+    assert_eq!(numbers.next().as_deref(), Some("[25]"));
+}
@@ -1,41 +1,211 @@
-use super::*;
+use super::{FONT_SIZE, PADDING};
 use crate::helper::*;
 use svg::node::element::Text;
 
-pub struct Edge(usize, usize, String, Markers);
+/// メッセージの種類
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum MessageKind {
+    /// 同期呼び出し (実線 + 塗りつぶし矢印)
+    Sync,
+    /// 非同期呼び出し (実線 + 開いた矢印)
+    Async,
+    /// 戻り (破線 + 開いた矢印)
+    Reply,
+    /// 生成 (破線 + 開いた矢印)
+    Create,
+    /// 送信先のないメッセージ (矢印 + 黒丸)
+    Lost,
+    /// 送信元のないメッセージ (黒丸から始まる)
+    Found,
+}
+
+impl MessageKind {
+    pub fn marker_end(&self) -> Markers {
+        match self {
+            MessageKind::Sync | MessageKind::Found => Markers::Array,
+            MessageKind::Async | MessageKind::Reply | MessageKind::Create => Markers::OpenArray,
+            MessageKind::Lost => Markers::ArrayCircle,
+        }
+    }
+
+    pub fn marker_start(&self) -> Markers {
+        match self {
+            MessageKind::Found => Markers::Circle,
+            _ => Markers::None,
+        }
+    }
+
+    #[inline]
+    pub fn is_dashed(&self) -> bool {
+        matches!(self, MessageKind::Reply | MessageKind::Create)
+    }
+}
+
+/// 複数行のラベルの行の高さ
+pub const LINE_HEIGHT: usize = FONT_SIZE + PADDING;
+
+/// 図の枠の左右どちらの辺か
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Gate {
+    Left,
+    Right,
+}
+
+/// メッセージの端が参加者ではなく図の枠にある場合の、その端
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum GateEnd {
+    /// 枠から参加者へ入ってくる
+    From(Gate),
+    /// 参加者から枠へ出ていく
+    To(Gate),
+}
+
+impl GateEnd {
+    #[inline]
+    pub fn side(&self) -> Gate {
+        match *self {
+            GateEnd::From(gate) | GateEnd::To(gate) => gate,
+        }
+    }
+}
+
+pub struct Edge {
+    from: usize,
+    to: usize,
+    text: String,
+    marker: Markers,
+    kind: MessageKind,
+    number: Option<String>,
+    max_width: Option<usize>,
+    gate: Option<GateEnd>,
+}
 
 impl Edge {
     pub fn new(x: usize, y: usize, s: String, m: Markers) -> Self {
-        Edge(x, y, s, m)
+        Edge {
+            from: x,
+            to: y,
+            text: s,
+            marker: m,
+            kind: MessageKind::Sync,
+            number: None,
+            max_width: None,
+            gate: None,
+        }
+    }
+
+    /// 種類に応じた矢印を持つメッセージ
+    pub fn message(x: usize, y: usize, s: String, kind: MessageKind) -> Self {
+        Edge {
+            marker: kind.marker_end(),
+            kind,
+            ..Edge::new(x, y, s, Markers::None)
+        }
+    }
+
+    /// 図の枠と参加者 node の間のメッセージ
+    pub fn gate(node: usize, gate: GateEnd, s: String, kind: MessageKind) -> Self {
+        Edge {
+            gate: Some(gate),
+            ..Edge::message(node, node, s, kind)
+        }
+    }
+
+    /// ラベルの前に付ける番号を設定する
+    pub fn numbered(mut self, number: Option<String>) -> Self {
+        self.number = number;
+        self
+    }
+
+    /// ラベルを幅 max_width 以内に折り返す
+    pub fn set_max_width(&mut self, max_width: usize) {
+        self.max_width = Some(max_width);
     }
 
     #[inline]
     pub fn node1(&self) -> usize {
-        self.0
+        self.from
     }
 
     #[inline]
     pub fn node2(&self) -> usize {
-        self.1
+        self.to
     }
 
+    /// 自分自身へのメッセージかどうか
     #[inline]
-    pub fn position_node1(&self, max_length: usize) -> (usize, usize) {
-        position(self.0, max_length)
+    pub fn is_self(&self) -> bool {
+        self.from == self.to
+            && self.gate.is_none()
+            && !matches!(self.kind, MessageKind::Lost | MessageKind::Found)
     }
 
     #[inline]
-    pub fn position_node2(&self, max_length: usize) -> (usize, usize) {
-        position(self.1, max_length)
+    pub fn gate_end(&self) -> Option<GateEnd> {
+        self.gate
+    }
+
+    #[inline]
+    pub fn text(&self) -> &str {
+        &self.text
     }
 
     #[inline]
     pub fn marker_type(&self) -> Markers {
-        self.3.clone()
+        self.marker.clone()
+    }
+
+    #[inline]
+    pub fn marker_start(&self) -> Markers {
+        self.kind.marker_start()
+    }
+
+    #[inline]
+    pub fn kind(&self) -> MessageKind {
+        self.kind
+    }
+
+    /// 番号を含めた表示用のラベル
+    pub fn label(&self) -> String {
+        match &self.number {
+            Some(number) => format!("{} {}", number, self.text()),
+            None => self.text().to_string(),
+        }
     }
 
-    pub fn make_text(&self) -> Text {
-        make_text(&self.2)
+    /// 表示する行の一覧。`\n` で改行し、最大幅があれば単語の区切りで折り返す。
+    pub fn lines(&self, metrics: &TextMetrics) -> Vec<String> {
+        let label = self.label();
+        match self.max_width {
+            Some(max_width) => label
+                .split('\n')
+                .flat_map(|line| metrics.wrap(line, max_width, FONT_SIZE))
+                .collect(),
+            None => label.split('\n').map(|line| line.to_string()).collect(),
+        }
+    }
+
+    /// 最も長い行の幅
+    pub fn label_width(&self, metrics: &TextMetrics) -> usize {
+        self.lines(metrics)
+            .iter()
+            .map(|line| metrics.width(line, FONT_SIZE))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// 2 行目以降の行の高さの合計
+    pub fn extra_height(&self, metrics: &TextMetrics) -> usize {
+        (self.lines(metrics).len() - 1) * LINE_HEIGHT
+    }
+
+    /// x を各行の左端 (text-anchor に従う) とするラベル
+    pub fn make_text(&self, x: usize, metrics: &TextMetrics) -> Text {
+        let lines = self.lines(metrics);
+        if lines.len() == 1 {
+            return make_text(&lines[0]);
+        }
+        make_multiline_text(lines.join("\n"), x, LINE_HEIGHT)
     }
 }
 
@@ -45,4 +215,37 @@ fn make_edge() {
     assert_eq!(edge.node1(), 0);
     assert_eq!(edge.node2(), 1);
     assert_eq!(edge.marker_type(), Markers::None);
+    assert_eq!(edge.kind(), MessageKind::Sync);
+    assert!(!edge.is_self());
+    assert!(Edge::new(2, 2, "".into(), Markers::None).is_self());
+}
+
+#[test]
+fn make_message() {
+    let reply = Edge::message(1, 0, "ok".into(), MessageKind::Reply);
+    assert_eq!(reply.marker_type(), Markers::OpenArray);
+    assert!(reply.kind().is_dashed());
+    let lost = Edge::message(1, 1, "".into(), MessageKind::Lost);
+    assert!(!lost.is_self());
+    assert_eq!(lost.marker_start(), Markers::None);
+    let found = Edge::message(1, 1, "".into(), MessageKind::Found);
+    assert_eq!(found.marker_start(), Markers::Circle);
+    let incoming = Edge::gate(1, GateEnd::From(Gate::Left), "".into(), MessageKind::Async);
+    assert!(!incoming.is_self());
+    assert_eq!(incoming.marker_type(), Markers::OpenArray);
+    assert_eq!(incoming.gate_end(), Some(GateEnd::From(Gate::Left)));
+    let numbered = Edge::new(0, 1, "go".into(), Markers::Array).numbered(Some("2.1".into()));
+    assert_eq!(numbered.text(), "go");
+    assert_eq!(numbered.label(), "2.1 go");
+}
+
+#[test]
+fn wrap_label() {
+    let mut edge = Edge::new(0, 1, "open the\nlong connection".into(), Markers::Array);
+    let metrics = TextMetrics::new();
+    assert_eq!(edge.lines(&metrics), vec!["open the", "long connection"]);
+    edge.set_max_width(FONT_SIZE * 10);
+    assert_eq!(edge.lines(&metrics), vec!["open the", "long", "connection"]);
+    assert_eq!(edge.label_width(&metrics), FONT_SIZE * 10);
+    assert_eq!(edge.extra_height(&metrics), LINE_HEIGHT * 2);
 }
@@ -0,0 +1,47 @@
+use std::fmt;
+
+/// シーケンス図を組み立てるときのエラー
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum SequenceError {
+    /// 登録されていない参加者の名前
+    UnknownParticipant(String),
+    /// 参加者を 1 人以上指定する必要がある
+    NoParticipants,
+    /// すでに他のグループに属している参加者
+    AlreadyGrouped(String),
+    /// 直前の行がメッセージではない
+    NoMessage,
+    /// 開いているフラグメントがない
+    NoOpenFragment,
+    /// 有効な activation がない参加者
+    NotActivated(String),
+    /// すでに破棄された参加者
+    Destroyed(String),
+    /// 生成メッセージより前に使われた参加者
+    UsedBeforeCreate(String),
+}
+
+impl fmt::Display for SequenceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SequenceError::UnknownParticipant(name) => write!(f, "unknown participant: {}", name),
+            SequenceError::NoParticipants => write!(f, "no participants are given"),
+            SequenceError::AlreadyGrouped(name) => {
+                write!(f, "participant is already in a group: {}", name)
+            }
+            SequenceError::NoMessage => write!(f, "the last row is not a message"),
+            SequenceError::NoOpenFragment => write!(f, "no fragment is open"),
+            SequenceError::NotActivated(name) => {
+                write!(f, "participant is not activated: {}", name)
+            }
+            SequenceError::Destroyed(name) => {
+                write!(f, "participant is already destroyed: {}", name)
+            }
+            SequenceError::UsedBeforeCreate(name) => {
+                write!(f, "participant is used before it is created: {}", name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SequenceError {}
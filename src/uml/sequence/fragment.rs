@@ -0,0 +1,95 @@
+/// 複合フラグメントの種類
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum FragmentKind {
+    Alt,
+    Opt,
+    Loop,
+    Par,
+    Break,
+    Critical,
+}
+
+impl FragmentKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            FragmentKind::Alt => "alt",
+            FragmentKind::Opt => "opt",
+            FragmentKind::Loop => "loop",
+            FragmentKind::Par => "par",
+            FragmentKind::Break => "break",
+            FragmentKind::Critical => "critical",
+        }
+    }
+}
+
+/// 複合フラグメント
+///
+/// `operands` は (ガード条件, 開始行) の組。`end` が `None` の間は閉じていない。
+pub struct Fragment {
+    kind: FragmentKind,
+    depth: usize,
+    operands: Vec<(String, usize)>,
+    end: Option<usize>,
+}
+
+impl Fragment {
+    pub fn new<T: Into<String>>(kind: FragmentKind, guard: T, depth: usize, start: usize) -> Self {
+        Fragment {
+            kind,
+            depth,
+            operands: vec![(guard.into(), start)],
+            end: None,
+        }
+    }
+
+    #[inline]
+    pub fn kind(&self) -> FragmentKind {
+        self.kind
+    }
+
+    #[inline]
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    #[inline]
+    pub fn start(&self) -> usize {
+        self.operands[0].1
+    }
+
+    #[inline]
+    pub fn end(&self) -> Option<usize> {
+        self.end
+    }
+
+    #[inline]
+    pub fn operands(&self) -> &[(String, usize)] {
+        &self.operands
+    }
+
+    pub fn add_operand<T: Into<String>>(&mut self, guard: T, row: usize) {
+        self.operands.push((guard.into(), row));
+    }
+
+    pub fn close(&mut self, end: usize) {
+        self.end = Some(end);
+    }
+
+    /// 指定した行がこのフラグメントの内側にあるかどうか
+    pub fn contains(&self, row: usize) -> bool {
+        self.start() < row && self.end.is_none_or(|end| row < end)
+    }
+}
+
+#[test]
+fn fragment_contains() {
+    let mut fragment = Fragment::new(FragmentKind::Alt, "ok", 0, 2);
+    fragment.add_operand("else", 4);
+    assert_eq!(fragment.operands().len(), 2);
+    fragment.close(6);
+    assert!(!fragment.contains(2));
+    assert!(fragment.contains(3));
+    assert!(fragment.contains(5));
+    assert!(!fragment.contains(6));
+    assert_eq!(fragment.kind().label(), "alt");
+}
@@ -0,0 +1,38 @@
+/// 参加者をまとめて囲む箱
+///
+/// `members` は参加者の番号。箱の中では members の順に並ぶ。
+pub struct NodeGroup {
+    title: String,
+    members: Vec<usize>,
+    color: String,
+}
+
+impl NodeGroup {
+    pub fn new<T: Into<String>>(title: T, members: Vec<usize>, color: &str) -> Self {
+        NodeGroup {
+            title: title.into(),
+            members,
+            color: color.to_string(),
+        }
+    }
+
+    #[inline]
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    #[inline]
+    pub fn members(&self) -> &[usize] {
+        &self.members
+    }
+
+    #[inline]
+    pub fn color(&self) -> &str {
+        &self.color
+    }
+
+    #[inline]
+    pub fn contains(&self, node: usize) -> bool {
+        self.members.contains(&node)
+    }
+}
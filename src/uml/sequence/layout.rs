@@ -0,0 +1,103 @@
+use super::*;
+
+/// 参加者と行の配置。描画の前に一度だけ計算する
+pub struct Layout {
+    /// 最初の参加者の箱の左端。左にはみ出す要素がすべて収まる位置
+    pub origin: usize,
+    /// 原点から見た列ごとのライフラインの x 座標
+    pub centers: Vec<usize>,
+    /// 行ごとの上端の y 座標。最後の要素はすべての行の下端
+    pub row_tops: Vec<usize>,
+    /// 図全体の (幅, 高さ)
+    pub size: (usize, usize),
+}
+
+impl Sequence {
+    /// ライフラインと行の位置を計算し、左にはみ出す要素がすべて収まるように原点を決める
+    pub(super) fn layout(&self) -> Layout {
+        let top = self.header_top() + self.header_height() - RECT_HEIGHT / 2;
+        let row_tops = std::iter::once(top)
+            .chain((0..self.rows.len()).scan(top, |y, index| {
+                *y += self.row_height(index);
+                Some(*y)
+            }))
+            .collect();
+        // 左にはみ出す幅を測るあいだは、はみ出しうる幅だけ右にずらして置いておく
+        let reach = self.overhang_bound();
+        let mut layout = Layout {
+            origin: reach,
+            centers: self.column_centers(),
+            row_tops,
+            size: (0, 0),
+        };
+        let left = self
+            .messages()
+            .map(|(index, e)| self.message_extent(&layout, index, e).0)
+            .chain(self.fragment_extents(&layout).iter().map(|&(left, _)| left))
+            .chain(
+                self.notes()
+                    .map(|(_, note)| self.note_extent(&layout, note).0),
+            )
+            .chain(self.refs().map(|(_, r)| self.ref_extent(&layout, r).0))
+            .min()
+            .unwrap_or(reach);
+        layout.origin = X_INDEX + reach.saturating_sub(left);
+        layout.size = self.diagram_size(&layout);
+        layout
+    }
+
+    /// 最初の参加者の箱より左にはみ出しうる幅の上限。
+    /// メッセージのラベル、ノート、ref 枠の幅とフラグメントの余白をすべて足して見積もる
+    fn overhang_bound(&self) -> usize {
+        let messages: usize = self
+            .messages()
+            .map(|(_, e)| {
+                e.label_width(&self.metrics) + LOST_LENGTH + PADDING * 2 + ACTIVATION_WIDTH
+            })
+            .sum();
+        let notes: usize = self
+            .notes()
+            .map(|(_, note)| note.width(&self.metrics) + ACTIVATION_WIDTH)
+            .sum();
+        let refs: usize = self
+            .refs()
+            .map(|(_, r)| REF_TAB_WIDTH + r.text_width(&self.metrics))
+            .sum();
+        messages + notes + refs + FRAGMENT_MARGIN * (self.fragments.len() + 1)
+    }
+
+    /// 図全体の (幅, 高さ)
+    fn diagram_size(&self, layout: &Layout) -> (usize, usize) {
+        let x = std::cmp::max(
+            (0..self.nodes.len())
+                .map(|index| self.node_x(layout, index) + self.node_width(index))
+                .max()
+                .unwrap_or(layout.origin)
+                + MARGIN * 2
+                + X_INDEX,
+            self.messages_right(layout) + X_INDEX,
+        )
+        .max(
+            self.fragment_extents(layout)
+                .iter()
+                .map(|&(_, right)| right + X_INDEX)
+                .max()
+                .unwrap_or(0),
+        )
+        .max(
+            self.groups
+                .iter()
+                .map(|group| self.group_extent(layout, group).1 + X_INDEX)
+                .max()
+                .unwrap_or(0),
+        )
+        .max(self.dividers_width());
+        let y = self.lifeline_bottom(layout) + self.header_height() + Y_INDEX;
+        let y = if self.groups.is_empty() {
+            y
+        } else {
+            y + MARGIN
+        };
+        (x, y)
+    }
+}
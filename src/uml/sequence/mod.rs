@@ -1,7 +1,30 @@
+mod activation;
+mod autonumber;
 mod edge;
+mod error;
+mod fragment;
+mod group;
+mod layout;
 mod node;
-use edge::Edge;
+mod note;
+mod reference;
+mod row;
+use activation::Activation;
+use autonumber::Autonumber;
+use edge::{Edge, GateEnd, LINE_HEIGHT};
+use fragment::Fragment;
+use group::NodeGroup;
+use layout::Layout;
 use node::Node;
+use note::{Note, NoteAnchor};
+use reference::Reference;
+use row::Row;
+
+pub use edge::{Gate, MessageKind};
+pub use error::SequenceError;
+pub use fragment::FragmentKind;
+pub use node::ParticipantKind;
+pub use note::NotePosition;
 
 const RECT_HEIGHT: usize = 20;
 const FONT_SIZE: usize = 8;
@@ -11,26 +34,24 @@ const X_INDEX: usize = 20;
 const Y_INDEX: usize = 20;
 const DEFAULT_HEIGHT: usize = 100;
 const VERTICAL_HEIGHT: usize = 30;
+const ACTIVATION_WIDTH: usize = 10;
+const ACTIVATION_OFFSET: usize = 4;
+const SELF_WIDTH: usize = 20;
+const SELF_HEIGHT: usize = 15;
+const LOST_LENGTH: usize = 40;
+const FRAGMENT_HEIGHT: usize = 24;
+const FRAGMENT_CORNER: usize = 4;
+const FRAGMENT_TAB_HEIGHT: usize = 12;
+const FRAGMENT_MARGIN: usize = 12;
+const DESTROY_SIZE: usize = 6;
+const GROUP_TITLE_HEIGHT: usize = 14;
+const DIVIDER_HEIGHT: usize = 24;
+const REF_TAB_WIDTH: usize = 3 * FONT_SIZE + PADDING * 2 + FRAGMENT_CORNER;
+const DELAY_HEIGHT: usize = 30;
 
 #[inline]
-fn rect_width(max_length: usize) -> usize {
-    FONT_SIZE * max_length + PADDING * 2
-}
-
-/// return (x, y)
-#[inline]
-fn position(index: usize, max_length: usize) -> (usize, usize) {
-    (position_x(index, max_length), position_y(index, max_length))
-}
-
-#[inline]
-fn position_x(index: usize, max_length: usize) -> usize {
-    X_INDEX + (max_length * FONT_SIZE + PADDING * 2 + MARGIN * 2) * index
-}
-
-#[inline]
-fn position_y(_index: usize, _max_length: usize) -> usize {
-    Y_INDEX
+fn rect_width(label_width: usize) -> usize {
+    label_width + PADDING * 2
 }
 
 use crate::{
@@ -39,39 +60,61 @@ use crate::{
     theme::{Theme, ThemeName},
     MakeSvg,
 };
-use std::collections::{HashMap, HashSet};
-use svg::node::element::Group;
+use std::collections::BTreeSet;
+use svg::node::element::path::Data;
+use svg::node::element::{Group, Path};
 use svg::Document;
 
 pub struct Sequence {
-    nodes: HashMap<Node, usize>,
-    edges: Vec<Edge>,
-    max_length: usize,
-    markers: HashSet<Markers>,
+    nodes: Vec<Node>,
+    /// 参加者ごとの表示する列の番号
+    columns: Vec<usize>,
+    rows: Vec<Row>,
+    activations: Vec<Activation>,
+    fragments: Vec<Fragment>,
+    open_fragments: Vec<usize>,
+    groups: Vec<NodeGroup>,
+    autonumber: Autonumber,
+    metrics: TextMetrics,
+    markers: BTreeSet<Markers>,
     theme: Theme,
 }
 
 impl Sequence {
     pub fn new(theme: ThemeName) -> Self {
         Sequence {
-            nodes: HashMap::new(),
-            edges: Vec::new(),
-            markers: HashSet::new(),
-            max_length: 0,
+            nodes: Vec::new(),
+            columns: Vec::new(),
+            rows: Vec::new(),
+            activations: Vec::new(),
+            fragments: Vec::new(),
+            open_fragments: Vec::new(),
+            groups: Vec::new(),
+            autonumber: Autonumber::new(),
+            markers: BTreeSet::new(),
+            metrics: TextMetrics::new(),
             theme: Theme::new(theme),
         }
     }
 
+    /// 文字列の幅の測り方を設定する。フォントを設定すると SVG にも font-family が入る。
+    pub fn set_metrics(&mut self, metrics: TextMetrics) -> &Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// 参加者を追加する。追加した順に左から並ぶ
     pub fn add_node(&mut self, text: &str) -> &Self {
-        self.nodes.insert(
-            Node {
-                name: text.to_string(),
-            },
-            self.nodes.len(),
-        );
-        if self.max_length < text.len() {
-            self.max_length = text.len();
+        self.add_participant(text, ParticipantKind::Participant)
+    }
+
+    /// ステレオタイプを指定して参加者を追加する
+    pub fn add_participant(&mut self, text: &str, kind: ParticipantKind) -> &Self {
+        if self.node_index(text).is_some() {
+            return self;
         }
+        self.columns.push(self.nodes.len());
+        self.nodes.push(Node::new(text, kind));
         self
     }
     pub fn add_nodes(&mut self, texts: Vec<&str>) -> &Self {
@@ -81,113 +124,1169 @@ impl Sequence {
         self
     }
 
-    pub fn add_edge(&mut self, edge: (&str, &str, &str, Markers)) -> &Self {
+    /// 参加者の並び順を変更する。
+    /// names に指定した参加者がその順で左に並び、残りは元の順序のまま右に続く。
+    pub fn reorder_nodes(&mut self, names: &[&str]) -> Result<&Self, SequenceError> {
+        let mut order: Vec<usize> = Vec::new();
+        for &name in names {
+            let index = self.find_node(name)?;
+            if !order.contains(&index) {
+                order.push(index);
+            }
+        }
+        let mut rest: Vec<usize> = (0..self.nodes.len())
+            .filter(|index| !order.contains(index))
+            .collect();
+        rest.sort_by_key(|&index| self.columns[index]);
+        order.extend(rest);
+        self.set_order(order);
+        Ok(self)
+    }
+
+    /// 参加者を column 列目に移動する
+    pub fn move_node(&mut self, name: &str, column: usize) -> Result<&Self, SequenceError> {
+        let index = self.find_node(name)?;
+        let mut order: Vec<usize> = (0..self.nodes.len()).collect();
+        order.sort_by_key(|&i| self.columns[i]);
+        order.retain(|&i| i != index);
+        order.insert(column.min(order.len()), index);
+        self.set_order(order);
+        Ok(self)
+    }
+
+    /// 参加者をまとめて色付きの箱で囲む。メンバーは指定した順に隣り合って並ぶ。
+    /// 他のグループに属している参加者は指定できない。
+    pub fn add_group(
+        &mut self,
+        title: &str,
+        members: &[&str],
+        color: &str,
+    ) -> Result<&Self, SequenceError> {
+        let indexes = self.find_nodes(members)?;
+        let mut members: Vec<usize> = Vec::with_capacity(indexes.len());
+        for index in indexes {
+            if self.groups.iter().any(|group| group.contains(index)) {
+                return Err(SequenceError::AlreadyGrouped(
+                    self.nodes[index].name.clone(),
+                ));
+            }
+            if !members.contains(&index) {
+                members.push(index);
+            }
+        }
+        self.groups.push(NodeGroup::new(title, members, color));
+        let mut order: Vec<usize> = (0..self.nodes.len()).collect();
+        order.sort_by_key(|&i| self.columns[i]);
+        self.set_order(order);
+        Ok(self)
+    }
+
+    /// 左から order の順に並べる。グループのメンバーは最初のメンバーの位置にまとめる。
+    fn set_order(&mut self, order: Vec<usize>) {
+        let mut placed: Vec<usize> = Vec::with_capacity(order.len());
+        for index in order {
+            if placed.contains(&index) {
+                continue;
+            }
+            match self.groups.iter().find(|group| group.contains(index)) {
+                Some(group) => placed.extend_from_slice(group.members()),
+                None => placed.push(index),
+            }
+        }
+        for (column, index) in placed.into_iter().enumerate() {
+            self.columns[index] = column;
+        }
+    }
+
+    #[inline]
+    fn node_index(&self, name: &str) -> Option<usize> {
+        self.nodes.iter().position(|node| node.name == name)
+    }
+
+    /// 登録されていない名前はエラーにする
+    fn find_node(&self, name: &str) -> Result<usize, SequenceError> {
+        self.node_index(name)
+            .ok_or_else(|| SequenceError::UnknownParticipant(name.to_string()))
+    }
+
+    /// 破棄された参加者もエラーにする
+    fn find_live_node(&self, name: &str) -> Result<usize, SequenceError> {
+        let index = self.find_node(name)?;
+        if self.nodes[index].destroyed.is_some() {
+            return Err(SequenceError::Destroyed(name.to_string()));
+        }
+        Ok(index)
+    }
+
+    /// index 番目の参加者がすでにメッセージか activation で使われているかどうか
+    fn is_used(&self, index: usize) -> bool {
+        self.messages()
+            .any(|(_, edge)| edge.node1() == index || edge.node2() == index)
+            || self.activations.iter().any(|a| a.node() == index)
+    }
+
+    /// 1 人以上の参加者の番号の一覧
+    fn find_nodes(&self, names: &[&str]) -> Result<Vec<usize>, SequenceError> {
+        if names.is_empty() {
+            return Err(SequenceError::NoParticipants);
+        }
+        names.iter().map(|&name| self.find_node(name)).collect()
+    }
+
+    pub fn add_edge(&mut self, edge: (&str, &str, &str, Markers)) -> Result<&Self, SequenceError> {
         let (start, end, text, marker) = edge;
+        let s = self.find_live_node(start)?;
+        let t = self.find_live_node(end)?;
         self.markers.insert(marker.clone());
-        let source_index = self.nodes.get(&Node { name: start.into() });
-        let target_index = self.nodes.get(&Node { name: end.into() });
+        let number = self.autonumber.next();
+        self.rows.push(Row::Message(
+            Edge::new(s, t, text.to_string(), marker).numbered(number),
+        ));
+        Ok(self)
+    }
+
+    pub fn add_edges(
+        &mut self,
+        edges: Vec<(&str, &str, &str, Markers)>,
+    ) -> Result<&Self, SequenceError> {
+        for edge in edges {
+            self.add_edge(edge)?;
+        }
+        Ok(self)
+    }
+
+    /// 種類を指定してメッセージを追加する。
+    /// `Lost` は送信元だけ、`Found` は送信先だけを使う。
+    /// `Reply` は送信元の最も内側の activation を終了させる。
+    pub fn add_message(
+        &mut self,
+        message: (&str, &str, &str, MessageKind),
+    ) -> Result<&Self, SequenceError> {
+        let (start, end, text, kind) = message;
+        let (start, end) = match kind {
+            MessageKind::Lost => (start, start),
+            MessageKind::Found => (end, end),
+            _ => (start, end),
+        };
+        let s = self.find_live_node(start)?;
+        let t = self.find_live_node(end)?;
+        if kind == MessageKind::Create && self.nodes[t].created.is_none() {
+            // 生成より前の行にはライフラインがない
+            if self.is_used(t) {
+                return Err(SequenceError::UsedBeforeCreate(end.to_string()));
+            }
+            self.nodes[t].created = Some(self.rows.len());
+        }
+        self.push_message(Edge::message(s, t, text.to_string(), kind));
+        if kind == MessageKind::Reply {
+            self.close_activation(s);
+        }
+        Ok(self)
+    }
+
+    pub fn add_messages(
+        &mut self,
+        messages: Vec<(&str, &str, &str, MessageKind)>,
+    ) -> Result<&Self, SequenceError> {
+        for message in messages {
+            self.add_message(message)?;
+        }
+        Ok(self)
+    }
+
+    /// 図の枠の gate 側から参加者へ入ってくるメッセージを追加する
+    pub fn add_incoming(
+        &mut self,
+        message: (Gate, &str, &str, MessageKind),
+    ) -> Result<&Self, SequenceError> {
+        let (gate, end, text, kind) = message;
+        let t = self.find_live_node(end)?;
+        self.push_message(Edge::gate(t, GateEnd::From(gate), text.to_string(), kind));
+        Ok(self)
+    }
+
+    /// 参加者から図の枠の gate 側へ出ていくメッセージを追加する
+    pub fn add_outgoing(
+        &mut self,
+        message: (&str, Gate, &str, MessageKind),
+    ) -> Result<&Self, SequenceError> {
+        let (start, gate, text, kind) = message;
+        let s = self.find_live_node(start)?;
+        self.push_message(Edge::gate(s, GateEnd::To(gate), text.to_string(), kind));
+        if kind == MessageKind::Reply {
+            self.close_activation(s);
+        }
+        Ok(self)
+    }
 
-        match (source_index, target_index) {
-            (Some(&s), Some(&t)) => self.edges.push(Edge::new(s, t, text.to_string(), marker)),
-            (_, _) => println!("invalid error"),
+    /// 番号を付けてメッセージの行を追加する
+    fn push_message(&mut self, edge: Edge) {
+        self.markers.insert(edge.marker_type());
+        self.markers.insert(edge.marker_start());
+        let number = self.autonumber.next();
+        self.rows.push(Row::Message(edge.numbered(number)));
+    }
+
+    /// 直前のメッセージのラベルを幅 max_width 以内に折り返す
+    pub fn wrap_message(&mut self, max_width: usize) -> Result<&Self, SequenceError> {
+        match self.rows.last_mut() {
+            Some(Row::Message(edge)) => edge.set_max_width(max_width),
+            _ => return Err(SequenceError::NoMessage),
         }
+        Ok(self)
+    }
+
+    /// 以降のメッセージに start から step ずつ番号を付ける
+    pub fn autonumber(&mut self, start: usize, step: usize) -> &Self {
+        self.autonumber.start(start, step);
         self
     }
 
-    pub fn add_edges(&mut self, edges: Vec<(&str, &str, &str, Markers)>) -> &Self {
-        for edge in edges {
-            self.add_edge(edge);
+    /// 番号の書式。`{}` が番号に置き換わる (例: `"[{}]"`)
+    pub fn autonumber_format(&mut self, format: &str) -> &Self {
+        self.autonumber.set_format(format);
+        self
+    }
+
+    /// フラグメントの中のメッセージを 1.1, 1.2 のように階層的に採番する
+    pub fn autonumber_hierarchical(&mut self, hierarchical: bool) -> &Self {
+        self.autonumber.set_hierarchical(hierarchical);
+        self
+    }
+
+    /// 採番を一時的に止める
+    pub fn stop_autonumber(&mut self) -> &Self {
+        self.autonumber.stop();
+        self
+    }
+
+    /// 止めた採番を続きの番号から再開する
+    pub fn resume_autonumber(&mut self) -> &Self {
+        self.autonumber.resume();
+        self
+    }
+
+    /// 直前のメッセージの位置から activation を開始する。
+    /// すでに有効な activation がある場合は入れ子になる。
+    pub fn activate(&mut self, name: &str) -> Result<&Self, SequenceError> {
+        let index = self.find_live_node(name)?;
+        let level = self
+            .activations
+            .iter()
+            .filter(|a| a.node() == index && a.is_open())
+            .count();
+        let start = self.rows.len().saturating_sub(1);
+        self.activations.push(Activation::new(index, level, start));
+        Ok(self)
+    }
+
+    /// 直前のメッセージの位置で最も内側の activation を終了する
+    pub fn deactivate(&mut self, name: &str) -> Result<&Self, SequenceError> {
+        let index = self.find_live_node(name)?;
+        if !self.close_activation(index) {
+            return Err(SequenceError::NotActivated(name.to_string()));
+        }
+        Ok(self)
+    }
+
+    /// index 番目の参加者の最も内側の activation を終了する。なければ false
+    fn close_activation(&mut self, index: usize) -> bool {
+        let end = self.rows.len().saturating_sub(1);
+        match self
+            .activations
+            .iter_mut()
+            .rev()
+            .find(|a| a.node() == index && a.is_open())
+        {
+            Some(activation) => {
+                activation.close(end);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 直前のメッセージの位置で参加者を破棄する。
+    /// ライフラインはそこで終わり、有効な activation もすべて終了する。
+    pub fn destroy(&mut self, name: &str) -> Result<&Self, SequenceError> {
+        let index = self.find_node(name)?;
+        let row = self.rows.len().saturating_sub(1);
+        let row = self.nodes[index]
+            .created
+            .map_or(row, |created| row.max(created));
+        self.nodes[index].destroyed = Some(row);
+        for activation in self
+            .activations
+            .iter_mut()
+            .filter(|a| a.node() == index && a.is_open())
+        {
+            activation.close(row);
         }
+        Ok(self)
+    }
+
+    /// 複合フラグメントを開始する。閉じるまでに追加したメッセージが枠で囲まれる。
+    pub fn begin_fragment(&mut self, kind: FragmentKind, guard: &str) -> &Self {
+        let index = self.fragments.len();
+        let depth = self.open_fragments.len();
+        self.fragments
+            .push(Fragment::new(kind, guard, depth, self.rows.len()));
+        self.open_fragments.push(index);
+        self.autonumber.begin_fragment();
+        self.rows.push(Row::FragmentStart);
+        self
+    }
+
+    /// 開いているフラグメントに新しいオペランド (`else` など) を追加する
+    pub fn else_fragment(&mut self, guard: &str) -> Result<&Self, SequenceError> {
+        let &index = self
+            .open_fragments
+            .last()
+            .ok_or(SequenceError::NoOpenFragment)?;
+        let row = self.rows.len();
+        self.fragments[index].add_operand(guard, row);
+        self.rows.push(Row::FragmentElse);
+        Ok(self)
+    }
+
+    /// 最も内側のフラグメントを閉じる
+    pub fn end_fragment(&mut self) -> Result<&Self, SequenceError> {
+        let index = self
+            .open_fragments
+            .pop()
+            .ok_or(SequenceError::NoOpenFragment)?;
+        self.fragments[index].close(self.rows.len());
+        self.autonumber.end_fragment();
+        self.rows.push(Row::FragmentEnd);
+        Ok(self)
+    }
+
+    /// 現在の行にノートを追加する
+    pub fn add_note(&mut self, position: NotePosition, text: &str) -> Result<&Self, SequenceError> {
+        let anchor = match position {
+            NotePosition::LeftOf(name) => NoteAnchor::LeftOf(self.find_node(name)?),
+            NotePosition::RightOf(name) => NoteAnchor::RightOf(self.find_node(name)?),
+            NotePosition::Over(names) => NoteAnchor::Over(self.find_nodes(names)?),
+        };
+        self.rows.push(Row::Note(Note::new(anchor, text)));
+        Ok(self)
+    }
+
+    /// names のライフラインを覆う `ref` 枠を追加する
+    pub fn add_ref(&mut self, names: &[&str], text: &str) -> Result<&Self, SequenceError> {
+        let nodes = self.find_nodes(names)?;
+        self.rows.push(Row::Ref(Reference::new(nodes, text)));
+        Ok(self)
+    }
+
+    /// 図全体を横切る区切り線 (`== text ==`) を追加する
+    pub fn add_divider(&mut self, text: &str) -> &Self {
+        self.rows.push(Row::Divider(text.to_string()));
+        self
+    }
+
+    /// 時間の経過 (`... text ...`) を追加する。その間のライフラインは点線になる。
+    pub fn add_delay(&mut self, text: &str) -> &Self {
+        self.rows.push(Row::Delay(text.to_string()));
+        self
+    }
+
+    /// 高さ height の空白を追加する
+    pub fn add_space(&mut self, height: usize) -> &Self {
+        self.rows.push(Row::Space(height));
         self
     }
 
-    fn make_nodes(&self) -> Vec<Group> {
-        let rect_width = rect_width(self.max_length);
-        let vertical_height = self.get_vertical_height();
+    /// index 番目の参加者の箱の左端
+    #[inline]
+    fn node_x(&self, layout: &Layout, index: usize) -> usize {
+        self.center_x(layout, index) - self.node_width(index) / 2
+    }
+
+    /// ライフラインの中心の x 座標
+    #[inline]
+    fn center_x(&self, layout: &Layout, index: usize) -> usize {
+        layout.origin + layout.centers[self.columns[index]]
+    }
+
+    /// index 番目の参加者の図形の幅
+    fn node_width(&self, index: usize) -> usize {
+        let node = &self.nodes[index];
+        node.width(rect_width(self.metrics.width(&node.name, FONT_SIZE)))
+    }
+
+    /// 原点から見た列ごとのライフラインの x 座標。
+    /// 隣り合うライフラインの間隔は、図形が重ならない幅とその間を通るメッセージのラベルの幅の大きい方になる。
+    fn column_centers(&self) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.nodes.len()).collect();
+        order.sort_by_key(|&index| self.columns[index]);
+        let widths: Vec<usize> = order.iter().map(|&index| self.node_width(index)).collect();
+        // (左の列, 右の列, 必要な間隔)
+        let mut spans = vec![];
+        for (_, edge) in self.messages() {
+            let (a, b) = (self.columns[edge.node1()], self.columns[edge.node2()]);
+            let label = edge.label_width(&self.metrics);
+            if edge.is_self() {
+                spans.push((a, a + 1, ACTIVATION_WIDTH + SELF_WIDTH + PADDING + label));
+            } else if a != b && edge.gate_end().is_none() {
+                spans.push((a.min(b), a.max(b), label + ACTIVATION_WIDTH + MARGIN * 2));
+            }
+        }
+        let mut centers: Vec<usize> = Vec::with_capacity(widths.len());
+        for (column, width) in widths.iter().enumerate() {
+            let center = match column {
+                0 => width / 2,
+                _ => {
+                    let adjacent =
+                        centers[column - 1] + (widths[column - 1] + width) / 2 + MARGIN * 2;
+                    spans
+                        .iter()
+                        .filter(|&&(_, right, _)| right == column)
+                        .map(|&(left, _, gap)| centers[left] + gap)
+                        .fold(adjacent, usize::max)
+                }
+            };
+            centers.push(center);
+        }
+        centers
+    }
+
+    /// ライフラインの上にある参加者の図形の高さ。途中で生成される参加者は含めない。
+    fn header_height(&self) -> usize {
         self.nodes
             .iter()
-            .map(|(node, &index)| {
-                let (x, y) = position(index, self.max_length);
-                let option = make_vec![
-                    ("fill", self.theme.color.rect.text),
-                    ("text-anchor", "middle"),
-                    ("dominant-baseline", "central"),
-                    ("font-size", FONT_SIZE)
-                ];
-                let text_element1 = node
-                    .make_svg()
-                    .position(x + rect_width / 2, y + RECT_HEIGHT / 2)
-                    .set_values(&option);
+            .filter(|node| node.created.is_none())
+            .map(|node| node.height())
+            .max()
+            .unwrap_or(RECT_HEIGHT)
+    }
+
+    /// 参加者の図形を並べる領域の上端。グループがあればタイトルの分だけ下げる。
+    fn header_top(&self) -> usize {
+        if self.groups.is_empty() {
+            Y_INDEX
+        } else {
+            Y_INDEX + GROUP_TITLE_HEIGHT
+        }
+    }
 
-                let text_element2 = node
-                    .make_svg()
-                    .position(x + rect_width / 2, y + vertical_height + RECT_HEIGHT / 2)
-                    .set_values(&option);
+    /// グループの箱の (左端, 右端)
+    fn group_extent(&self, layout: &Layout, group: &NodeGroup) -> (usize, usize) {
+        let members = group.members().iter();
+        let left = members
+            .clone()
+            .map(|&node| self.node_x(layout, node))
+            .min()
+            .unwrap_or(0)
+            .saturating_sub(MARGIN);
+        let right = members
+            .map(|&node| self.node_x(layout, node) + self.node_width(node))
+            .max()
+            .unwrap_or(0)
+            + MARGIN;
+        let title = self.metrics.width(group.title(), FONT_SIZE) + PADDING * 2;
+        (left, right.max(left + title))
+    }
 
-                let rect_element1 = (rect_width, RECT_HEIGHT)
-                    .make_rect()
-                    .position(x, y)
-                    .set("rx", 2usize)
-                    .set("ry", 2usize)
-                    .set("fill", self.theme.color.rect.fill)
-                    .set("stroke", self.theme.color.rect.frame)
-                    .set("stroke-width", 1usize);
-                let rect_element2 = rect_element1.clone().set("y", y + vertical_height);
+    /// ライフラインの下端
+    fn lifeline_bottom(&self, layout: &Layout) -> usize {
+        self.header_top() + self.header_height() - RECT_HEIGHT + self.get_vertical_height(layout)
+    }
 
-                Group::new()
-                    .add(rect_element1)
-                    .add(text_element1)
-                    .add(rect_element2)
-                    .add(text_element2)
+    /// index 番目の参加者の頭の図形の上端
+    fn head_top(&self, layout: &Layout, index: usize) -> usize {
+        let node = &self.nodes[index];
+        match node.created {
+            Some(row) => self.row_y(layout, row) - node.height() / 2,
+            None => self.header_top() + self.header_height() - node.height(),
+        }
+    }
+
+    /// index 番目の参加者のライフラインの (上端, 下端)
+    fn lifeline(&self, layout: &Layout, index: usize) -> (usize, usize) {
+        let node = &self.nodes[index];
+        let top = self.head_top(layout, index) + node.height();
+        let bottom = node
+            .destroyed
+            .map_or(self.lifeline_bottom(layout), |row| self.row_y(layout, row));
+        (top, bottom.max(top))
+    }
+
+    /// (行番号, ノート) の一覧
+    fn notes(&self) -> impl Iterator<Item = (usize, &Note)> {
+        self.rows
+            .iter()
+            .enumerate()
+            .filter_map(|(index, row)| match row {
+                Row::Note(note) => Some((index, note)),
+                _ => None,
+            })
+    }
+
+    /// ノートの (左端, 右端)
+    fn note_extent(&self, layout: &Layout, note: &Note) -> (usize, usize) {
+        let width = note.width(&self.metrics);
+        match *note.anchor() {
+            NoteAnchor::LeftOf(node) => {
+                let right = self.center_x(layout, node).saturating_sub(ACTIVATION_WIDTH);
+                (right.saturating_sub(width), right)
+            }
+            NoteAnchor::RightOf(node) => {
+                let left = self.center_x(layout, node) + ACTIVATION_WIDTH;
+                (left, left + width)
+            }
+            NoteAnchor::Over(ref nodes) => {
+                let centers = nodes.iter().map(|&node| self.center_x(layout, node));
+                let lo = centers.clone().min().unwrap_or(0);
+                let hi = centers.max().unwrap_or(0);
+                let width = width.max(hi - lo + MARGIN * 4);
+                let left = ((lo + hi) / 2).saturating_sub(width / 2);
+                (left, left + width)
+            }
+        }
+    }
+
+    /// (行番号, ref 枠) の一覧
+    fn refs(&self) -> impl Iterator<Item = (usize, &Reference)> {
+        self.rows
+            .iter()
+            .enumerate()
+            .filter_map(|(index, row)| match row {
+                Row::Ref(reference) => Some((index, reference)),
+                _ => None,
+            })
+    }
+
+    /// ref 枠の (左端, 右端)。覆う参加者の図形の幅に広げる。
+    fn ref_extent(&self, layout: &Layout, reference: &Reference) -> (usize, usize) {
+        let nodes = reference.nodes().iter();
+        let lo = nodes
+            .clone()
+            .map(|&node| self.node_x(layout, node))
+            .min()
+            .unwrap_or(0);
+        let hi = nodes
+            .map(|&node| self.node_x(layout, node) + self.node_width(node))
+            .max()
+            .unwrap_or(0);
+        let label = REF_TAB_WIDTH + reference.text_width(&self.metrics);
+        let width = (hi - lo).max(label);
+        let left = ((lo + hi) / 2).saturating_sub(width / 2);
+        (left, left + width)
+    }
+
+    /// 図の枠の左端。gate はここに接続する。
+    #[inline]
+    fn frame_left(&self) -> usize {
+        X_INDEX / 2
+    }
+
+    /// 図の枠の右端
+    fn frame_right(&self, layout: &Layout) -> usize {
+        layout.size.0 - X_INDEX / 2
+    }
+
+    /// (行番号, メッセージ) の一覧
+    fn messages(&self) -> impl Iterator<Item = (usize, &Edge)> {
+        self.rows
+            .iter()
+            .enumerate()
+            .filter_map(|(index, row)| row.message().map(|edge| (index, edge)))
+    }
+
+    /// row 行目で node 上の最も内側の activation
+    fn active_bar(&self, node: usize, row: usize) -> Option<&Activation> {
+        self.activations
+            .iter()
+            .filter(|a| a.node() == node && a.contains(row))
+            .max_by_key(|a| a.level())
+    }
+
+    /// メッセージの端点の x 座標。activation があればその左右の端に接続する。
+    fn attach_x(&self, layout: &Layout, node: usize, row: usize, to_right: bool) -> usize {
+        bar_edge_x(
+            self.center_x(layout, node),
+            self.active_bar(node, row),
+            to_right,
+        )
+    }
+
+    /// 自己呼び出しの (出発点, 戻り点) の x 座標。
+    /// この行で開始した activation には戻り側だけが接続する。
+    fn self_attach_x(&self, layout: &Layout, node: usize, row: usize) -> (usize, usize) {
+        let outer = self
+            .activations
+            .iter()
+            .filter(|a| a.node() == node && a.start() < row && a.contains(row))
+            .max_by_key(|a| a.level());
+        let center = self.center_x(layout, node);
+        (
+            bar_edge_x(center, outer, true),
+            self.attach_x(layout, node, row, true),
+        )
+    }
+
+    /// 横線の両端の x 座標
+    fn message_x(&self, layout: &Layout, index: usize, value: &Edge) -> (usize, usize) {
+        if let Some(gate) = value.gate_end() {
+            let node = value.node1();
+            return match gate {
+                GateEnd::From(Gate::Left) => {
+                    (self.frame_left(), self.attach_x(layout, node, index, false))
+                }
+                GateEnd::From(Gate::Right) => (
+                    self.frame_right(layout),
+                    self.attach_x(layout, node, index, true),
+                ),
+                GateEnd::To(Gate::Left) => {
+                    (self.attach_x(layout, node, index, false), self.frame_left())
+                }
+                GateEnd::To(Gate::Right) => (
+                    self.attach_x(layout, node, index, true),
+                    self.frame_right(layout),
+                ),
+            };
+        }
+        match value.kind() {
+            MessageKind::Lost => {
+                let x = self.attach_x(layout, value.node1(), index, true);
+                (x, x + LOST_LENGTH)
+            }
+            MessageKind::Found => {
+                let x = self.attach_x(layout, value.node2(), index, false);
+                (x.saturating_sub(LOST_LENGTH).max(PADDING), x)
+            }
+            _ => {
+                let to_right = self.columns[value.node1()] < self.columns[value.node2()];
+                let target = value.node2();
+                let x2 = if self.nodes[target].created == Some(index) {
+                    // 生成メッセージは生成された図形の端に接続する
+                    let half = self.nodes[target].body_width(self.node_width(target)) / 2;
+                    let center = self.center_x(layout, target);
+                    if to_right {
+                        center - half
+                    } else {
+                        center + half
+                    }
+                } else {
+                    self.attach_x(layout, target, index, !to_right)
+                };
+                (self.attach_x(layout, value.node1(), index, to_right), x2)
+            }
+        }
+    }
+
+    /// メッセージの描画範囲 (左端, 右端)。ラベルも含む。
+    fn message_extent(&self, layout: &Layout, index: usize, value: &Edge) -> (usize, usize) {
+        // gate への線は枠まで伸びるので、ラベルが収まる範囲だけを確保する
+        if let Some(gate) = value.gate_end() {
+            let to_right = gate.side() == Gate::Right;
+            let x = self.attach_x(layout, value.node1(), index, to_right);
+            let length = value.label_width(&self.metrics).max(LOST_LENGTH) + PADDING * 2;
+            return if to_right {
+                (x, x + length)
+            } else {
+                (x.saturating_sub(length), x)
+            };
+        }
+        if value.is_self() {
+            let (x_out, x_in) = self.self_attach_x(layout, value.node1(), index);
+            let right = x_out.max(x_in) + SELF_WIDTH + PADDING + value.label_width(&self.metrics);
+            return (x_out.min(x_in), right);
+        }
+        let (x1, x2) = self.message_x(layout, index, value);
+        let (left, right) = (x1.min(x2), x1.max(x2));
+        let half_text = value.label_width(&self.metrics) / 2;
+        let mid = (left + right) / 2;
+        let right = match value.kind() {
+            MessageKind::Lost => right + FONT_SIZE,
+            _ => right,
+        };
+        (
+            left.min(mid.saturating_sub(half_text)),
+            right.max(mid + half_text),
+        )
+    }
+
+    /// ライフラインの外にはみ出すメッセージ (自己呼び出し, lost) やノートの右端
+    fn messages_right(&self, layout: &Layout) -> usize {
+        self.messages()
+            .map(|(index, e)| self.message_extent(layout, index, e).1)
+            .chain(
+                self.notes()
+                    .map(|(_, note)| self.note_extent(layout, note).1),
+            )
+            .chain(self.refs().map(|(_, r)| self.ref_extent(layout, r).1))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// 各フラグメントの枠の (左端, 右端)。内側のフラグメントから順に計算する。
+    fn fragment_extents(&self, layout: &Layout) -> Vec<(usize, usize)> {
+        let mut extents = vec![(0usize, 0usize); self.fragments.len()];
+        let mut order: Vec<usize> = (0..self.fragments.len()).collect();
+        order.sort_by_key(|&index| std::cmp::Reverse(self.fragments[index].depth()));
+        for index in order {
+            let fragment = &self.fragments[index];
+            let mut content: Option<(usize, usize)> = None;
+            let mut extend = |(l, r): (usize, usize)| {
+                content = Some(content.map_or((l, r), |(cl, cr)| (cl.min(l), cr.max(r))));
+            };
+            for (row, edge) in self.messages().filter(|(row, _)| fragment.contains(*row)) {
+                let nodes = [edge.node1(), edge.node2()];
+                for node in nodes {
+                    let x = self.center_x(layout, node);
+                    extend((x, x));
+                }
+                extend(self.message_extent(layout, row, edge));
+            }
+            for (inner, other) in self.fragments.iter().enumerate() {
+                if other.depth() == fragment.depth() + 1 && fragment.contains(other.start()) {
+                    let (l, r) = extents[inner];
+                    extend((l.saturating_sub(FRAGMENT_MARGIN), r + FRAGMENT_MARGIN));
+                }
+            }
+            let (l, r) = content.unwrap_or_else(|| {
+                let centers = (0..self.nodes.len()).map(|node| self.center_x(layout, node));
+                (
+                    centers.clone().min().unwrap_or(0),
+                    centers.max().unwrap_or(0),
+                )
+            });
+            let left = l.saturating_sub(FRAGMENT_MARGIN);
+            let label = self.fragment_label_width(fragment);
+            extents[index] = (left, (r + FRAGMENT_MARGIN).max(left + label));
+        }
+        extents
+    }
+
+    // 参加者の図形をライフラインの上下に描く。破棄された参加者は下側に描かない。
+    fn make_nodes(&self, layout: &Layout) -> Vec<Group> {
+        let bottom = self.lifeline_bottom(layout);
+        self.nodes
+            .iter()
+            .enumerate()
+            .map(|(index, node)| {
+                let x = self.center_x(layout, index);
+                let width = self.node_width(index);
+                let top = self.head_top(layout, index);
+                let group = Group::new().add(node.make_shape(x, top, width, false, &self.theme));
+                if node.destroyed.is_some() {
+                    return group;
+                }
+                group.add(node.make_shape(x, bottom, width, true, &self.theme))
+            })
+            .collect()
+    }
+
+    // 破棄された位置に × を描く
+    fn make_destructions(&self, layout: &Layout) -> Vec<Group> {
+        self.nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, node)| node.destroyed.is_some())
+            .map(|(index, _)| {
+                let (x, y) = (self.center_x(layout, index), self.lifeline(layout, index).1);
+                let data = Data::new()
+                    .move_to((x - DESTROY_SIZE, y - DESTROY_SIZE))
+                    .line_to((x + DESTROY_SIZE, y + DESTROY_SIZE))
+                    .move_to((x + DESTROY_SIZE, y - DESTROY_SIZE))
+                    .line_to((x - DESTROY_SIZE, y + DESTROY_SIZE));
+                let cross = Path::new()
+                    .set("d", data)
+                    .set("stroke", self.theme.color.line.primary)
+                    .set("stroke-width", 2);
+                Group::new().add(cross)
+            })
+            .collect()
+    }
+
+    /// index 行目が占める高さ
+    fn row_height(&self, index: usize) -> usize {
+        match &self.rows[index] {
+            Row::Message(edge) if edge.is_self() => VERTICAL_HEIGHT + self.self_height(edge),
+            // 生成された図形の下半分の分だけ広げる
+            Row::Message(edge) if self.nodes[edge.node2()].created == Some(index) => {
+                VERTICAL_HEIGHT
+                    + edge.extra_height(&self.metrics)
+                    + self.nodes[edge.node2()].height() / 2
+            }
+            Row::Message(edge) => VERTICAL_HEIGHT + edge.extra_height(&self.metrics),
+            Row::FragmentStart | Row::FragmentElse | Row::FragmentEnd => FRAGMENT_HEIGHT,
+            Row::Note(note) => note.height() + MARGIN * 2,
+            Row::Ref(reference) => reference.height() + MARGIN * 2,
+            Row::Divider(_) => DIVIDER_HEIGHT,
+            Row::Delay(_) => DELAY_HEIGHT,
+            Row::Space(height) => *height,
+        }
+    }
+
+    /// index 行目の上端の y 座標
+    fn row_top(&self, layout: &Layout, index: usize) -> usize {
+        layout.row_tops[index]
+    }
+
+    /// index 行目の基準の y 座標
+    /// (メッセージは横線、フラグメントは枠線、ノートは上端、区切りと経過は中央の位置)
+    fn row_y(&self, layout: &Layout, index: usize) -> usize {
+        match self.rows.get(index) {
+            // 複数行のラベルは横線の上に積む
+            Some(Row::Message(edge)) if !edge.is_self() => {
+                self.row_top(layout, index) + VERTICAL_HEIGHT + edge.extra_height(&self.metrics)
+            }
+            Some(Row::Message(_)) | None => self.row_top(layout, index) + VERTICAL_HEIGHT,
+            Some(Row::Note(_) | Row::Ref(_)) => self.row_top(layout, index) + MARGIN,
+            Some(Row::FragmentStart | Row::FragmentElse | Row::FragmentEnd) => {
+                self.row_top(layout, index) + FRAGMENT_HEIGHT / 2
+            }
+            Some(Row::Divider(_)) => self.row_top(layout, index) + DIVIDER_HEIGHT / 2,
+            Some(Row::Delay(_)) => self.row_top(layout, index) + DELAY_HEIGHT / 2,
+            Some(Row::Space(_)) => self.row_top(layout, index),
+        }
+    }
+
+    /// 時間の経過を表す行の (上端, 下端) の一覧
+    fn delays(&self, layout: &Layout) -> Vec<(usize, usize)> {
+        self.rows
+            .iter()
+            .enumerate()
+            .filter(|(_, row)| matches!(row, Row::Delay(_)))
+            .map(|(index, _)| {
+                let top = self.row_top(layout, index);
+                (top, top + DELAY_HEIGHT)
             })
             .collect()
     }
 
-    fn get_vertical_height(&self) -> usize {
-        std::cmp::max(DEFAULT_HEIGHT, (self.edges.len() + 1) * VERTICAL_HEIGHT)
+    /// 区切り線のラベルが収まる図の幅
+    fn dividers_width(&self) -> usize {
+        self.rows
+            .iter()
+            .filter_map(|row| match row {
+                Row::Divider(text) => {
+                    Some(self.metrics.width(text, FONT_SIZE) + PADDING * 2 + X_INDEX * 2)
+                }
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn get_vertical_height(&self, layout: &Layout) -> usize {
+        let rows = layout.row_tops[self.rows.len()] - layout.row_tops[0];
+        std::cmp::max(DEFAULT_HEIGHT, rows + VERTICAL_HEIGHT)
+    }
+
+    // グループの箱をライフラインの後ろに描く
+    fn make_groups(&self, layout: &Layout) -> Vec<Group> {
+        let bottom = self.lifeline_bottom(layout) + self.header_height() + MARGIN;
+        self.groups
+            .iter()
+            .map(|group| {
+                let (left, right) = self.group_extent(layout, group);
+                let rect = (right - left, bottom - Y_INDEX)
+                    .make_rect()
+                    .position(left, Y_INDEX)
+                    .set("fill", group.color())
+                    .set("stroke", self.theme.color.rect.frame)
+                    .set("stroke-width", 1);
+                let title = make_text(group.title())
+                    .position((left + right) / 2, Y_INDEX + GROUP_TITLE_HEIGHT / 2)
+                    .set("text-anchor", "middle")
+                    .set("dominant-baseline", "central")
+                    .set("font-weight", "bold")
+                    .set("fill", self.theme.color.text_primary)
+                    .set("font-size", FONT_SIZE);
+                Group::new().add(rect).add(title)
+            })
+            .collect()
     }
 
-    // 縦線を引く
-    fn make_vertical_lines(&self) -> Vec<Group> {
-        let height = self.get_vertical_height() - RECT_HEIGHT;
+    // 縦線を引く。時間の経過の行では点線にする。
+    fn make_vertical_lines(&self, layout: &Layout) -> Vec<Group> {
+        let delays = self.delays(layout);
         self.nodes
             .iter()
             .enumerate()
             .map(|(index, _)| {
-                let (mut x, mut y) = position(index, self.max_length);
-                x += rect_width(self.max_length) / 2;
-                y += RECT_HEIGHT;
-                let path = (x, y, x, y + height)
-                    .make_line()
-                    // .set("stroke-dasharray", "4")
-                    .set("stroke", self.theme.color.line.second);
-                println!("{}", self.theme.color.line.second);
-                Group::new().add(path)
+                let x = self.center_x(layout, index);
+                let (top, bottom) = self.lifeline(layout, index);
+                let mut group = Group::new();
+                let mut y = top;
+                for &(start, end) in delays.iter().filter(|&&(s, e)| s < bottom && top < e) {
+                    let (start, end) = (start.max(top), end.min(bottom));
+                    if y < start {
+                        group = group.add(
+                            (x, y, x, start)
+                                .make_line()
+                                .set("stroke", self.theme.color.line.second),
+                        );
+                    }
+                    group = group.add(
+                        (x, start, x, end)
+                            .make_line()
+                            .set("stroke", self.theme.color.line.second)
+                            .set("stroke-dasharray", "2"),
+                    );
+                    y = end;
+                }
+                if y < bottom {
+                    group = group.add(
+                        (x, y, x, bottom)
+                            .make_line()
+                            .set("stroke", self.theme.color.line.second),
+                    );
+                }
+                group
             })
             .collect()
     }
 
-    // 横線を引く
-    fn make_horizontal_lines(&self) -> Vec<Group> {
-        self.edges
+    // 区切り線と時間の経過のラベルを描く
+    fn make_dividers(&self, layout: &Layout) -> Vec<Group> {
+        let (width, _) = layout.size;
+        let (left, right) = (X_INDEX / 2, width - X_INDEX / 2);
+        let center = width / 2;
+        let text_option = make_vec![
+            ("fill", self.theme.color.text_primary),
+            ("text-anchor", "middle"),
+            ("dominant-baseline", "central"),
+            ("font-size", FONT_SIZE)
+        ];
+        self.rows
             .iter()
             .enumerate()
+            .filter_map(|(index, row)| {
+                let y = self.row_y(layout, index);
+                match row {
+                    Row::Divider(text) => {
+                        let lines = [y - 1, y + 1].map(|y| {
+                            (left, y, right, y)
+                                .make_line()
+                                .set("stroke", self.theme.color.rect.frame)
+                        });
+                        let label_width = self.metrics.width(text, FONT_SIZE) + PADDING * 2;
+                        let label = (label_width, FONT_SIZE + PADDING * 2)
+                            .make_rect()
+                            .position(center - label_width / 2, y - FONT_SIZE / 2 - PADDING)
+                            .set_theme(&self.theme);
+                        let text = make_text(text)
+                            .position(center, y)
+                            .set("font-weight", "bold")
+                            .set_values(&text_option);
+                        let [upper, lower] = lines;
+                        Some(Group::new().add(upper).add(lower).add(label).add(text))
+                    }
+                    Row::Delay(text) if !text.is_empty() => {
+                        let text = make_text(text)
+                            .position(center, y)
+                            .set("font-style", "italic")
+                            .set_values(&text_option);
+                        Some(Group::new().add(text))
+                    }
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+
+    // activation の長方形を描く
+    fn make_activations(&self, layout: &Layout) -> Vec<Group> {
+        self.activations
+            .iter()
+            .map(|activation| {
+                let bottom = self.lifeline(layout, activation.node()).1;
+                let x = self.center_x(layout, activation.node()) - ACTIVATION_WIDTH / 2
+                    + ACTIVATION_OFFSET * activation.level();
+                let start = activation.start();
+                let mut y1 = self.row_y(layout, start);
+                // 自己呼び出しで開始した場合は戻ってきた位置から始める
+                if let Some(edge) = self
+                    .rows
+                    .get(start)
+                    .and_then(Row::message)
+                    .filter(|e| e.is_self() && e.node1() == activation.node())
+                {
+                    y1 += self.self_height(edge);
+                }
+                let y2 = activation
+                    .end()
+                    .map_or(bottom, |end| {
+                        self.row_y(layout, end).max(y1 + VERTICAL_HEIGHT / 2)
+                    })
+                    .max(y1);
+                let rect = (ACTIVATION_WIDTH, y2 - y1)
+                    .make_rect()
+                    .position(x, y1)
+                    .set_theme(&self.theme);
+                Group::new().add(rect)
+            })
+            .collect()
+    }
+
+    // 複合フラグメントの枠を描く
+    fn make_fragments(&self, layout: &Layout) -> Vec<Group> {
+        let extents = self.fragment_extents(layout);
+        let bottom = self.row_top(layout, self.rows.len()) + FRAGMENT_HEIGHT / 2;
+        self.fragments
+            .iter()
+            .zip(extents)
+            .map(|(fragment, (left, right))| {
+                let top = self.row_y(layout, fragment.start());
+                let y_end = fragment.end().map_or(bottom, |end| self.row_y(layout, end));
+                let frame = (right - left, y_end - top)
+                    .make_rect()
+                    .position(left, top)
+                    .set("fill", "none")
+                    .set("stroke", self.theme.color.rect.frame)
+                    .set("stroke-width", 1);
+                let tab_width = self.fragment_tab_width(fragment);
+                let tab = Data::new()
+                    .move_to((left, top))
+                    .horizontal_line_to(left + tab_width)
+                    .vertical_line_to(top + FRAGMENT_TAB_HEIGHT - FRAGMENT_CORNER)
+                    .line_to((
+                        left + tab_width - FRAGMENT_CORNER,
+                        top + FRAGMENT_TAB_HEIGHT,
+                    ))
+                    .horizontal_line_to(left)
+                    .close();
+                let tab = Path::new()
+                    .set("d", tab)
+                    .set("fill", self.theme.color.rect.fill)
+                    .set("stroke", self.theme.color.rect.frame)
+                    .set("stroke-width", 1);
+                let text_option = make_vec![
+                    ("fill", self.theme.color.text_primary),
+                    ("dominant-baseline", "central"),
+                    ("font-size", FONT_SIZE)
+                ];
+                let label = make_text(fragment.kind().label())
+                    .position(left + PADDING, top + FRAGMENT_TAB_HEIGHT / 2)
+                    .set("font-weight", "bold")
+                    .set_values(&text_option);
+                let mut group = Group::new().add(frame).add(tab).add(label);
+                for (index, (guard, row)) in fragment.operands().iter().enumerate() {
+                    let y = self.row_y(layout, *row);
+                    let x = if index == 0 {
+                        left + tab_width + PADDING
+                    } else {
+                        let separator = (left, y, right, y)
+                            .make_line()
+                            .set("stroke", self.theme.color.rect.frame)
+                            .set("stroke-dasharray", "4");
+                        group = group.add(separator);
+                        left + PADDING
+                    };
+                    if !guard.is_empty() {
+                        let guard = make_text(format!("[{}]", guard))
+                            .position(x, y + FRAGMENT_TAB_HEIGHT / 2)
+                            .set_values(&text_option);
+                        group = group.add(guard);
+                    }
+                }
+                group
+            })
+            .collect()
+    }
+
+    // ref 枠をライフラインの上に描く
+    fn make_refs(&self, layout: &Layout) -> Vec<Group> {
+        self.refs()
+            .map(|(index, reference)| {
+                let (left, right) = self.ref_extent(layout, reference);
+                let (top, height) = (self.row_y(layout, index), reference.height());
+                let frame = (right - left, height)
+                    .make_rect()
+                    .position(left, top)
+                    .set_theme(&self.theme);
+                let tab = Data::new()
+                    .move_to((left, top))
+                    .horizontal_line_to(left + REF_TAB_WIDTH)
+                    .vertical_line_to(top + FRAGMENT_TAB_HEIGHT - FRAGMENT_CORNER)
+                    .line_to((
+                        left + REF_TAB_WIDTH - FRAGMENT_CORNER,
+                        top + FRAGMENT_TAB_HEIGHT,
+                    ))
+                    .horizontal_line_to(left);
+                let tab = Path::new()
+                    .set("d", tab)
+                    .set("fill", "none")
+                    .set("stroke", self.theme.color.rect.frame)
+                    .set("stroke-width", 1);
+                let label = make_text("ref")
+                    .position(left + PADDING, top + FRAGMENT_TAB_HEIGHT / 2)
+                    .set("font-weight", "bold")
+                    .set("dominant-baseline", "central")
+                    .set("fill", self.theme.color.text_primary)
+                    .set("font-size", FONT_SIZE);
+                let text = make_multiline_text(reference.text(), (left + right) / 2, LINE_HEIGHT)
+                    .set("y", top + FRAGMENT_TAB_HEIGHT + PADDING + FONT_SIZE)
+                    .set("text-anchor", "middle")
+                    .set("fill", self.theme.color.text_primary)
+                    .set("font-size", FONT_SIZE);
+                Group::new().add(frame).add(tab).add(label).add(text)
+            })
+            .collect()
+    }
+
+    // gate があるときは図の枠を描く
+    fn make_frame(&self, layout: &Layout) -> Option<Group> {
+        let has_gate = self.messages().any(|(_, edge)| edge.gate_end().is_some());
+        if !has_gate {
+            return None;
+        }
+        let (width, height) = layout.size;
+        let frame = (width - X_INDEX, height - Y_INDEX)
+            .make_rect()
+            .position(X_INDEX / 2, Y_INDEX / 2)
+            .set("fill", "none")
+            .set("stroke", self.theme.color.rect.frame)
+            .set("stroke-width", 1);
+        Some(Group::new().add(frame))
+    }
+
+    // ノートを描く
+    fn make_notes(&self, layout: &Layout) -> Vec<Group> {
+        self.notes()
+            .map(|(index, note)| {
+                let (left, right) = self.note_extent(layout, note);
+                let (width, height) = (right - left, note.height());
+                let shape = make_note(width, height).set_theme(&self.theme);
+                let text = make_multiline_text(note.text(), PADDING, FONT_SIZE + PADDING)
+                    .set("y", PADDING + FONT_SIZE)
+                    .set("fill", self.theme.color.text_primary)
+                    .set("font-size", FONT_SIZE);
+                Group::new()
+                    .add(shape)
+                    .add(text)
+                    .transform(left, self.row_y(layout, index))
+            })
+            .collect()
+    }
+
+    // 横線を引く
+    fn make_horizontal_lines(&self, layout: &Layout) -> Vec<Group> {
+        self.messages()
             .map(|(index, value)| {
-                let mut x1 = value.position_node1(self.max_length).0;
-                let mut x2 = value.position_node2(self.max_length).0;
-                let (_, y) = position(index, self.max_length);
-                let y_path = y + RECT_HEIGHT / 2 + VERTICAL_HEIGHT * (index + 1);
-                x1 += rect_width(self.max_length) >> 1;
-                x2 += rect_width(self.max_length) >> 1;
-                let path = (x1, y_path, x2, y_path)
+                if value.is_self() {
+                    return self.make_self_message(layout, index, value);
+                }
+                let (x1, x2) = self.message_x(layout, index, value);
+                let y_path = self.row_y(layout, index);
+                let mut path = (x1, y_path, x2, y_path)
                     .make_line()
                     .set("stroke", self.theme.color.line.primary)
+                    .add_marker_start(&value.marker_start())
                     .add_marker_end(&value.marker_type());
+                if value.kind().is_dashed() {
+                    path = path.set("stroke-dasharray", "4");
+                }
                 let x_mid = (x1 + x2) >> 1;
                 let x = x_mid;
-                let y = y_path - FONT_SIZE;
+                let y = y_path - FONT_SIZE - value.extra_height(&self.metrics);
                 let text_element = value
-                    .make_text()
+                    .make_text(x, &self.metrics)
                     .position(x, y)
                     .set("text-anchor", "middle")
                     .set("fill", self.theme.color.text_primary)
@@ -196,37 +1295,138 @@ impl Sequence {
             })
             .collect()
     }
+
+    // 自分自身へのメッセージは右側に折り返す
+    fn make_self_message(&self, layout: &Layout, index: usize, value: &Edge) -> Group {
+        let (x_out, x_in) = self.self_attach_x(layout, value.node1(), index);
+        let x_loop = x_out.max(x_in) + SELF_WIDTH;
+        let y1 = self.row_y(layout, index);
+        let y2 = y1 + self.self_height(value);
+        let data = Data::new()
+            .move_to((x_out, y1))
+            .horizontal_line_to(x_loop)
+            .vertical_line_to(y2)
+            .horizontal_line_to(x_in);
+        let mut path = Path::new()
+            .set("d", data)
+            .set("fill", "none")
+            .set("stroke", self.theme.color.line.primary)
+            .add_marker_end(&value.marker_type());
+        if value.kind().is_dashed() {
+            path = path.set("stroke-dasharray", "4");
+        }
+        let text_element = value
+            .make_text(x_loop + PADDING, &self.metrics)
+            .position(
+                x_loop + PADDING,
+                (y1 + y2 - value.extra_height(&self.metrics)) / 2,
+            )
+            .set("text-anchor", "start")
+            .set("dominant-baseline", "central")
+            .set("fill", self.theme.color.text_primary)
+            .set("font-size", FONT_SIZE);
+        Group::new().add(path).add(text_element)
+    }
+
+    /// 自己呼び出しの折り返しの高さ。ラベルの行数に合わせて伸びる。
+    #[inline]
+    fn self_height(&self, edge: &Edge) -> usize {
+        SELF_HEIGHT.max(edge.lines(&self.metrics).len() * LINE_HEIGHT)
+    }
+
+    /// フラグメントのラベルのタブの幅
+    #[inline]
+    fn fragment_tab_width(&self, fragment: &Fragment) -> usize {
+        self.metrics.width(fragment.kind().label(), FONT_SIZE) + PADDING * 2 + FRAGMENT_CORNER
+    }
+
+    /// 枠の左端から、タブとすべてのオペランドの `[guard]` が収まる幅
+    fn fragment_label_width(&self, fragment: &Fragment) -> usize {
+        let tab_width = self.fragment_tab_width(fragment);
+        fragment
+            .operands()
+            .iter()
+            .enumerate()
+            .filter(|(_, (guard, _))| !guard.is_empty())
+            .map(|(index, (guard, _))| {
+                // 最初のオペランドの条件はタブの右に、それ以降は枠の左端に置く
+                let x = if index == 0 { tab_width } else { 0 };
+                x + PADDING * 2 + self.metrics.width(&format!("[{}]", guard), FONT_SIZE)
+            })
+            .fold(tab_width, usize::max)
+    }
+}
+
+/// activation の左右の端。activation がなければライフラインの中心。
+fn bar_edge_x(center: usize, activation: Option<&Activation>, to_right: bool) -> usize {
+    match activation {
+        Some(activation) => {
+            let left = center - ACTIVATION_WIDTH / 2 + ACTIVATION_OFFSET * activation.level();
+            if to_right {
+                left + ACTIVATION_WIDTH
+            } else {
+                left
+            }
+        }
+        None => center,
+    }
 }
 
 impl MakeSvg for Sequence {
     fn make_svg(&mut self) -> Document {
+        let layout = &self.layout();
         let mut sequence_group = Group::new();
-        for vline in self.make_vertical_lines() {
+        if let Some(family) = self.metrics.font_family() {
+            sequence_group = sequence_group.set("font-family", family);
+        }
+        if let Some(frame) = self.make_frame(layout) {
+            sequence_group = sequence_group.add(frame);
+        }
+        for group in self.make_groups(layout) {
+            sequence_group = sequence_group.add(group);
+        }
+        for vline in self.make_vertical_lines(layout) {
             sequence_group = sequence_group.add(vline);
         }
-        for node in self.make_nodes() {
+        for activation in self.make_activations(layout) {
+            sequence_group = sequence_group.add(activation);
+        }
+        for fragment in self.make_fragments(layout) {
+            sequence_group = sequence_group.add(fragment);
+        }
+        for reference in self.make_refs(layout) {
+            sequence_group = sequence_group.add(reference);
+        }
+        for node in self.make_nodes(layout) {
             sequence_group = sequence_group.add(node);
         }
-        for hline in self.make_horizontal_lines() {
+        for destruction in self.make_destructions(layout) {
+            sequence_group = sequence_group.add(destruction);
+        }
+        for divider in self.make_dividers(layout) {
+            sequence_group = sequence_group.add(divider);
+        }
+        for note in self.make_notes(layout) {
+            sequence_group = sequence_group.add(note);
+        }
+        for hline in self.make_horizontal_lines(layout) {
             sequence_group = sequence_group.add(hline);
         }
         use svg::node::element::Definitions;
         let mut defs = Definitions::new();
-        for markers in self.markers.iter() {
+        for markers in self.markers.iter().filter(|&m| *m != Markers::None) {
             let marker_svg = markers.make_svg();
             defs = defs.add(marker_svg);
         }
 
         Document::new()
-            .set("viewBox", self.bounding_box())
+            .set("viewBox", (0, 0, layout.size.0, layout.size.1))
             .add(defs)
             .add(sequence_group)
     }
 
     fn bounding_box(&self) -> (usize, usize, usize, usize) {
-        let x = 2 * X_INDEX
-            + (self.max_length * FONT_SIZE + PADDING * 2 + MARGIN * 2) * self.nodes.len();
-        let y = 2 * Y_INDEX + RECT_HEIGHT * 2 + self.get_vertical_height();
-        (0, 0, x, y)
+        let (width, height) = self.layout().size;
+        (0, 0, width, height)
     }
 }
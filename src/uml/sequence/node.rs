@@ -1,13 +1,226 @@
+use super::{FONT_SIZE, PADDING, RECT_HEIGHT};
 use crate::helper::*;
-use svg::node::element::Text;
+use crate::make_vec;
+use crate::theme::Theme;
+use svg::node::element::path::Data;
+use svg::node::element::{Group, Path, Text};
+
+const ICON_SIZE: usize = 16;
+const ICON_WIDTH: usize = 24;
+const LABEL_HEIGHT: usize = 12;
+const CYLINDER_DEPTH: usize = 4;
+
+/// 参加者のステレオタイプ
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
+pub enum ParticipantKind {
+    Participant,
+    Actor,
+    Boundary,
+    Control,
+    Entity,
+    Database,
+    Queue,
+}
 
 #[derive(PartialEq, Eq, Hash)]
 pub struct Node {
     pub name: String,
+    pub kind: ParticipantKind,
+    /// 生成メッセージの行番号。None なら最初から存在する
+    pub created: Option<usize>,
+    /// 破棄された行番号。None なら最後まで存在する
+    pub destroyed: Option<usize>,
 }
 
 impl Node {
+    pub fn new(name: &str, kind: ParticipantKind) -> Self {
+        Node {
+            name: name.to_string(),
+            kind,
+            created: None,
+            destroyed: None,
+        }
+    }
+
     pub fn make_svg(&self) -> Text {
         make_text(&self.name)
     }
+
+    /// ラベルの幅が label_width のときの図形の幅
+    pub fn width(&self, label_width: usize) -> usize {
+        match self.kind {
+            ParticipantKind::Participant | ParticipantKind::Database => label_width,
+            ParticipantKind::Queue => label_width + CYLINDER_DEPTH * 2,
+            _ => label_width.max(ICON_WIDTH),
+        }
+    }
+
+    /// 図形の高さ
+    pub fn height(&self) -> usize {
+        match self.kind {
+            ParticipantKind::Participant | ParticipantKind::Queue => RECT_HEIGHT,
+            ParticipantKind::Database => RECT_HEIGHT + CYLINDER_DEPTH * 2,
+            ParticipantKind::Actor => ICON_WIDTH + LABEL_HEIGHT,
+            _ => ICON_SIZE + PADDING + LABEL_HEIGHT,
+        }
+    }
+
+    /// メッセージが接続する本体の幅。アイコン型はアイコンの幅になる。
+    pub fn body_width(&self, width: usize) -> usize {
+        match self.kind {
+            ParticipantKind::Participant | ParticipantKind::Database | ParticipantKind::Queue => {
+                width
+            }
+            _ => ICON_SIZE,
+        }
+    }
+
+    /// 中心 cx, 上端 top, 幅 width の図形を描く。
+    /// mirrored のときはライフラインの下側用にラベルをアイコンの上に置く。
+    pub fn make_shape(
+        &self,
+        cx: usize,
+        top: usize,
+        width: usize,
+        mirrored: bool,
+        theme: &Theme,
+    ) -> Group {
+        let option = make_vec![
+            ("fill", theme.color.rect.text),
+            ("text-anchor", "middle"),
+            ("dominant-baseline", "central"),
+            ("font-size", FONT_SIZE)
+        ];
+        let left = cx - width / 2;
+        let height = self.height();
+        match self.kind {
+            ParticipantKind::Participant => {
+                let rect = (width, height)
+                    .make_rect()
+                    .position(left, top)
+                    .set("rx", 2usize)
+                    .set("ry", 2usize)
+                    .set_theme(theme);
+                let text = self
+                    .make_svg()
+                    .position(cx, top + height / 2)
+                    .set_values(&option);
+                Group::new().add(rect).add(text)
+            }
+            ParticipantKind::Database => {
+                let (right, bottom, e) = (left + width, top + height, CYLINDER_DEPTH);
+                let rx = width / 2;
+                let body = Data::new()
+                    .move_to((left, top + e))
+                    .elliptical_arc_to((rx, e, 0, 0, 0, right, top + e))
+                    .elliptical_arc_to((rx, e, 0, 0, 0, left, top + e))
+                    .vertical_line_to(bottom - e)
+                    .elliptical_arc_to((rx, e, 0, 0, 0, right, bottom - e))
+                    .vertical_line_to(top + e);
+                let body = Path::new().set("d", body).set_theme(theme);
+                let text = self
+                    .make_svg()
+                    .position(cx, top + (height + e) / 2)
+                    .set_values(&option);
+                Group::new().add(body).add(text)
+            }
+            ParticipantKind::Queue => {
+                let (right, bottom, e) = (left + width, top + height, CYLINDER_DEPTH);
+                let ry = height / 2;
+                let body = Data::new()
+                    .move_to((left + e, top))
+                    .horizontal_line_to(right - e)
+                    .elliptical_arc_to((e, ry, 0, 0, 1, right - e, bottom))
+                    .horizontal_line_to(left + e)
+                    .elliptical_arc_to((e, ry, 0, 0, 1, left + e, top))
+                    .close()
+                    .move_to((right - e, top))
+                    .elliptical_arc_to((e, ry, 0, 0, 0, right - e, bottom));
+                let body = Path::new().set("d", body).set_theme(theme);
+                let text = self
+                    .make_svg()
+                    .position(cx - e / 2, top + height / 2)
+                    .set_values(&option);
+                Group::new().add(body).add(text)
+            }
+            _ => {
+                let icon_height = height - LABEL_HEIGHT;
+                let (icon_top, label_y) = if mirrored {
+                    (top + LABEL_HEIGHT, top + LABEL_HEIGHT / 2)
+                } else {
+                    (top, top + icon_height + LABEL_HEIGHT / 2)
+                };
+                let text = self.make_svg().position(cx, label_y).set_values(&option);
+                Group::new()
+                    .add(self.make_icon(cx, icon_top, theme))
+                    .add(text)
+            }
+        }
+    }
+
+    /// アイコン型の図形 (actor, boundary, control, entity)
+    fn make_icon(&self, cx: usize, top: usize, theme: &Theme) -> Group {
+        let r = ICON_SIZE / 2;
+        let stroke = |path: Path| {
+            path.set("fill", "none")
+                .set("stroke", theme.color.rect.frame)
+                .set("stroke-width", 1)
+        };
+        match self.kind {
+            ParticipantKind::Actor => {
+                let head = (cx, top + 4, 4).make_circle().set_theme(theme);
+                let body = Data::new()
+                    .move_to((cx, top + 8))
+                    .vertical_line_to(top + 16)
+                    .line_to((cx - 6, top + 23))
+                    .move_to((cx, top + 16))
+                    .line_to((cx + 6, top + 23))
+                    .move_to((cx - 7, top + 11))
+                    .horizontal_line_to(cx + 7);
+                Group::new()
+                    .add(head)
+                    .add(stroke(Path::new().set("d", body)))
+            }
+            ParticipantKind::Boundary => {
+                let circle = (cx + 3, top + r, r).make_circle().set_theme(theme);
+                let bar = Data::new()
+                    .move_to((cx - r - 3, top))
+                    .vertical_line_to(top + ICON_SIZE)
+                    .move_to((cx - r - 3, top + r))
+                    .horizontal_line_to(cx + 3 - r);
+                Group::new()
+                    .add(stroke(Path::new().set("d", bar)))
+                    .add(circle)
+            }
+            ParticipantKind::Control => {
+                let circle = (cx, top + r + PADDING, r).make_circle().set_theme(theme);
+                let arrow = Data::new()
+                    .move_to((cx + 3, top))
+                    .line_to((cx, top + PADDING))
+                    .line_to((cx + 3, top + PADDING * 2));
+                Group::new()
+                    .add(circle)
+                    .add(stroke(Path::new().set("d", arrow)))
+            }
+            _ => {
+                let circle = (cx, top + r, r).make_circle().set_theme(theme);
+                let line = Data::new()
+                    .move_to((cx - r, top + ICON_SIZE + 1))
+                    .horizontal_line_to(cx + r);
+                Group::new()
+                    .add(circle)
+                    .add(stroke(Path::new().set("d", line)))
+            }
+        }
+    }
+}
+
+#[test]
+fn participant_sizes() {
+    let node = |kind| Node::new("a", kind);
+    assert_eq!(node(ParticipantKind::Participant).height(), RECT_HEIGHT);
+    assert!(node(ParticipantKind::Actor).height() > RECT_HEIGHT);
+    assert_eq!(node(ParticipantKind::Actor).width(4), ICON_WIDTH);
+    assert_eq!(node(ParticipantKind::Database).width(40), 40);
+    assert_eq!(node(ParticipantKind::Entity).body_width(40), ICON_SIZE);
 }
@@ -0,0 +1,60 @@
+use super::{FONT_SIZE, PADDING};
+use crate::helper::{TextMetrics, NOTE_FOLD};
+
+/// ノートを置く位置
+pub enum NotePosition<'a> {
+    LeftOf(&'a str),
+    RightOf(&'a str),
+    /// 指定した参加者のライフラインをまたぐ
+    Over(&'a [&'a str]),
+}
+
+/// 参加者の番号に解決したノートの位置
+pub enum NoteAnchor {
+    LeftOf(usize),
+    RightOf(usize),
+    Over(Vec<usize>),
+}
+
+pub struct Note {
+    anchor: NoteAnchor,
+    text: String,
+}
+
+impl Note {
+    pub fn new<T: Into<String>>(anchor: NoteAnchor, text: T) -> Self {
+        Note {
+            anchor,
+            text: text.into(),
+        }
+    }
+
+    #[inline]
+    pub fn anchor(&self) -> &NoteAnchor {
+        &self.anchor
+    }
+
+    #[inline]
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn width(&self, metrics: &TextMetrics) -> usize {
+        metrics.lines_width(&self.text, FONT_SIZE) + PADDING * 2 + NOTE_FOLD
+    }
+
+    pub fn height(&self) -> usize {
+        let lines = self.text.lines().count().max(1);
+        lines * (FONT_SIZE + PADDING) + PADDING * 2
+    }
+}
+
+#[test]
+fn note_size() {
+    let note = Note::new(NoteAnchor::LeftOf(0), "ab\nabcd");
+    assert_eq!(
+        note.width(&TextMetrics::new()),
+        4 * FONT_SIZE + PADDING * 2 + NOTE_FOLD
+    );
+    assert!(note.height() > Note::new(NoteAnchor::RightOf(0), "ab").height());
+}
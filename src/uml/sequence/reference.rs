@@ -0,0 +1,54 @@
+use super::edge::LINE_HEIGHT;
+use super::{FONT_SIZE, FRAGMENT_TAB_HEIGHT, PADDING};
+use crate::helper::TextMetrics;
+
+/// 別のシーケンス図を参照する `ref` 枠
+///
+/// `nodes` は枠が覆うライフラインの参加者の番号。
+pub struct Reference {
+    nodes: Vec<usize>,
+    text: String,
+}
+
+impl Reference {
+    pub fn new<T: Into<String>>(nodes: Vec<usize>, text: T) -> Self {
+        Reference {
+            nodes,
+            text: text.into(),
+        }
+    }
+
+    #[inline]
+    pub fn nodes(&self) -> &[usize] {
+        &self.nodes
+    }
+
+    #[inline]
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// 枠の高さ。タブの下に本文の行が並ぶ。
+    pub fn height(&self) -> usize {
+        FRAGMENT_TAB_HEIGHT + self.text.lines().count().max(1) * LINE_HEIGHT + PADDING * 2
+    }
+
+    /// 本文が収まる幅
+    pub fn text_width(&self, metrics: &TextMetrics) -> usize {
+        metrics.lines_width(&self.text, FONT_SIZE) + PADDING * 2
+    }
+}
+
+#[test]
+fn reference_size() {
+    let reference = Reference::new(vec![0, 1], "Login\nflow");
+    assert_eq!(reference.nodes(), &[0, 1]);
+    assert_eq!(
+        reference.height(),
+        FRAGMENT_TAB_HEIGHT + LINE_HEIGHT * 2 + PADDING * 2
+    );
+    assert_eq!(
+        reference.text_width(&TextMetrics::new()),
+        5 * FONT_SIZE + PADDING * 2
+    );
+}
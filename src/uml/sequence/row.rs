@@ -0,0 +1,33 @@
+use super::edge::Edge;
+use super::note::Note;
+use super::reference::Reference;
+
+/// シーケンス図の 1 行分の要素
+pub enum Row {
+    Message(Edge),
+    /// 複合フラグメントの開始
+    FragmentStart,
+    /// 複合フラグメントの区切り (`else` など)
+    FragmentElse,
+    /// 複合フラグメントの終了
+    FragmentEnd,
+    Note(Note),
+    /// 他の図を参照する `ref` 枠
+    Ref(Reference),
+    /// 図全体を横切る区切り線
+    Divider(String),
+    /// 時間の経過。ライフラインを点線にする
+    Delay(String),
+    /// 指定した高さの空白
+    Space(usize),
+}
+
+impl Row {
+    #[inline]
+    pub fn message(&self) -> Option<&Edge> {
+        match self {
+            Row::Message(edge) => Some(edge),
+            _ => None,
+        }
+    }
+}